@@ -1,5 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use kyros::{self, utils::gpu_eval, Config};
+use kyros::execution::{cpu_eval, gpu_eval};
+use kyros::structs::{Config, MathFrame, Strategy};
 
 fn image_20k_test() -> () {
     let mut config = Config::default();
@@ -25,10 +26,45 @@ fn image_1024_test() -> () {
 
 }
 
+/// Renders a 1024px image of the whole set on the CPU with `strategy`.
+fn cpu_1024_test(strategy: Strategy) -> () {
+    let config = Config {
+        size_x: 1024,
+        size_y: 1024,
+        max_i: 4096,
+        gpu: false,
+        travel_distance: false,
+        strategy,
+        math_frame: MathFrame {
+            factor_x: 4.0 / 1023.0,
+            factor_y: 4.0 / 1023.0,
+            shear_x: 0.0,
+            shear_y: 0.0,
+            offset_x: -2.0,
+            offset_y: -2.0,
+        },
+        ..Config::default()
+    };
+
+    // CPU generation blocks a worker thread while the compression runs on the others
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        cpu_eval(&config).await.unwrap();
+    });
+
+}
+
 
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("Generate 20kpx", |b| b.iter(|| image_20k_test()));
     c.bench_function("Generate 1024pk", |b| b.iter(|| image_1024_test()));
+
+    // Compares brute force CPU generation with subdivision
+    let mut group = c.benchmark_group("CPU Generate 1024px");
+    group.sample_size(10);
+    group.bench_function("Pixels", |b| b.iter(|| cpu_1024_test(Strategy::Pixels)));
+    group.bench_function("Subdivide", |b| b.iter(|| cpu_1024_test(Strategy::Subdivide)));
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
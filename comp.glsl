@@ -4,12 +4,51 @@ layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
 
 layout(set = 0, binding = 0, rgba8) uniform writeonly image2D Data;
 
+// The table of palette colors from palette.rs (packed RGBA bytes)
+layout(set = 0, binding = 1) readonly buffer Palette {
+    uint palette[];
+};
+
+// The image used by image orbit traps from orbit_trap.rs (how dark each pixel is)
+layout(set = 0, binding = 2) readonly buffer TrapImage {
+    float trap_image[];
+};
+
+// The settings which change between images without changing the shader (`GpuSettings` in structs.rs)
+layout(set = 0, binding = 3) readonly buffer Settings {
+    vec4 background;
+    vec4 foreground;
+    vec2 trap_direction;
+    float trap_radius;
+    float escape_limit;
+    float boundary_width;
+    float light_angle;
+    float light_height;
+    float rate_of_color_change;
+    float color_cycle;
+    uint width;
+    uint max_i;
+    uint samples;
+} settings;
+
+// The distribution of color values from histogram.rs (a single unused value without histogram coloring)
+layout(set = 0, binding = 4) readonly buffer Histogram {
+    float histogram[];
+};
+
 layout(push_constant) uniform Params {
-    highp float factor_x;
-    highp float factor_y;
-    highp float offset_x;
-    highp float offset_y;
+    {{ float }} factor_x;
+    {{ float }} factor_y;
+    {{ float }} shear_x;
+    {{ float }} shear_y;
+    {{ float }} offset_x;
+    {{ float }} offset_y;
     highp uint amnt_of_lines;
+    {{ float }} c_real;
+    {{ float }} c_imaginary;
+    {{ float }} trap_center_real;
+    {{ float }} trap_center_imaginary;
+    {{ float }} periodicity_tolerance;
 } params;
 
 vec3 hsv_to_rgb(vec3 c) {
@@ -23,8 +62,55 @@ float colors(float n) {
     {{ colors }}
 }
 
-// From shadows.rs
-float shadows(float n) {
+// From color.rs (used for pixels which converged to a root)
+float root_colors(float n, int root, int roots) {
+    {{ root_colors }}
+}
+
+{%- if histogram %}
+// Gets the fraction of pixels with a color value below `n` (same as `Histogram::equalize`)
+float equalize(float n) {
+    int low = 0;
+    int high = histogram.length();
+    while (low < high) {
+        int middle = (low + high) / 2;
+        if (histogram[middle] <= n) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    if (low == 0) {
+        return 0.0;
+    }
+    if (low == histogram.length()) {
+        return 1.0;
+    }
+    return (float(low - 1) + (n - histogram[low - 1]) / (histogram[low] - histogram[low - 1])) / float(histogram.length() - 1);
+}
+{%- endif %}
+
+{%- if orbit_trap %}
+// Settings for the orbit trap
+#define trap_center {{ vec2 }}(params.trap_center_real, params.trap_center_imaginary)
+#define trap_direction settings.trap_direction
+#define trap_radius settings.trap_radius
+
+// Gets the distance from `z` to the orbit trap in units of its radius (same as `Trap::distance`)
+float trap_distance({{ vec2 }} z) {
+    vec2 p = vec2(z - trap_center);
+    float along = dot(p, trap_direction);
+    float across = p.y * trap_direction.x - p.x * trap_direction.y;
+    {{ trap_method }}
+}
+{%- endif %}
+
+// Settings for the NORMAL shadow
+#define light_angle settings.light_angle
+#define light_height settings.light_height
+
+// From shadows.rs (`normal` is the direction away from the set if the derivative is tracked)
+float shadows(float n, vec2 normal) {
     {{ shadows }}
 }
 
@@ -37,7 +123,7 @@ void write_data(vec4 res) {
 }
 
 struct Complex {
-    vec2 data; // data.x => real & data.y => imaginary
+    {{ vec2 }} data; // data.x => real & data.y => imaginary
 };
 
 Complex add(Complex n1, Complex n2) {
@@ -48,84 +134,376 @@ Complex add(Complex n1, Complex n2) {
 
 Complex mult(Complex n1, Complex n2) {
     return Complex(
-        vec2(
+        {{ vec2 }}(
             n1.data.x * n2.data.x - n1.data.y * n2.data.y,
             n1.data.y * n2.data.x + n1.data.x * n2.data.y
         )
     );
 }
 
-void main() {
+Complex sub(Complex n1, Complex n2) {
+    return Complex(
+        n1.data - n2.data
+    );
+}
+
+Complex negate(Complex n) {
+    return Complex(
+        -n.data
+    );
+}
+
+Complex divide(Complex n1, Complex n2) {
+    return Complex(
+        {{ vec2 }}(
+            n1.data.x * n2.data.x + n1.data.y * n2.data.y,
+            n1.data.y * n2.data.x - n1.data.x * n2.data.y
+        ) / dot(n2.data, n2.data)
+    );
+}
+
+// Helpers for user defined formulas & powers (From expression.rs & formula.rs)
+Complex number({{ float }} real) {
+    return Complex({{ vec2 }}(real, 0.0));
+}
+
+Complex imaginary_unit() {
+    return Complex({{ vec2 }}(0.0, 1.0));
+}
+
+// Raises n to a whole number power by repeated squaring
+Complex power(Complex n, int p) {
+    Complex result = number(1.0);
+    Complex base = n;
+    if (p < 0) {
+        base = divide(number(1.0), n);
+        p = -p;
+    }
+    while (p > 0) {
+        if ((p & 1) == 1) {
+            result = mult(result, base);
+        }
+        base = mult(base, base);
+        p >>= 1;
+    }
+    return result;
+}
+
+// Transcendental functions are only available for floats so doubles get converted
+Complex c_exp(Complex n) {
+    vec2 data = vec2(n.data);
+    return Complex({{ vec2 }}(exp(data.x) * vec2(cos(data.y), sin(data.y))));
+}
+
+Complex c_log(Complex n) {
+    vec2 data = vec2(n.data);
+    return Complex({{ vec2 }}(log(length(data)), atan(data.y, data.x)));
+}
+
+Complex power(Complex n, {{ float }} p) {
+    vec2 data = vec2(n.data);
+    float angle = atan(data.y, data.x) * float(p);
+    return Complex({{ vec2 }}(pow(length(data), float(p)) * vec2(cos(angle), sin(angle))));
+}
+
+Complex power(Complex n, Complex p) {
+    return c_exp(mult(p, c_log(n)));
+}
+
+Complex c_sqrt(Complex n) {
+    vec2 data = vec2(n.data);
+    float angle = atan(data.y, data.x) / 2.0;
+    return Complex({{ vec2 }}(sqrt(length(data)) * vec2(cos(angle), sin(angle))));
+}
+
+Complex c_sin(Complex n) {
+    vec2 data = vec2(n.data);
+    return Complex({{ vec2 }}(sin(data.x) * cosh(data.y), cos(data.x) * sinh(data.y)));
+}
+
+Complex c_cos(Complex n) {
+    vec2 data = vec2(n.data);
+    return Complex({{ vec2 }}(cos(data.x) * cosh(data.y), -sin(data.x) * sinh(data.y)));
+}
+
+Complex c_tan(Complex n) {
+    return divide(c_sin(n), c_cos(n));
+}
+
+Complex c_sinh(Complex n) {
+    vec2 data = vec2(n.data);
+    return Complex({{ vec2 }}(sinh(data.x) * cos(data.y), cosh(data.x) * sin(data.y)));
+}
 
-    highp ivec2 global_dimensions = ivec2({{ width }}, params.amnt_of_lines); // global size
-    highp ivec2 local_dimensions = ivec2(imageSize(Data)); // local size
+Complex c_cosh(Complex n) {
+    vec2 data = vec2(n.data);
+    return Complex({{ vec2 }}(cosh(data.x) * cos(data.y), sinh(data.x) * sin(data.y)));
+}
+
+Complex c_tanh(Complex n) {
+    return divide(c_sinh(n), c_cosh(n));
+}
+
+Complex c_abs(Complex n) {
+    return number(length(n.data));
+}
+
+Complex conj(Complex n) {
+    return Complex({{ vec2 }}(n.data.x, -n.data.y));
+}
+
+Complex real_part(Complex n) {
+    return number(n.data.x);
+}
+
+Complex imaginary_part(Complex n) {
+    return number(n.data.y);
+}
+
+// The size of the step used for numeric derivatives
+const {{ float }} derivative_step = {{ derivative_step }};
+
+// From formula.rs
+Complex formula(Complex z, Complex c) {
+    {{ formula }}
+    return z;
+}
+
+// From formula.rs (the derivative of the next z with respect to the pixel position)
+Complex derivative(Complex z, Complex c, Complex dz, {{ float }} dc) {
+    {{ derivative }}
+}
+
+// From escape.rs
+bool escaped(Complex z, Complex previous_z, float limit) {
+    {{ escape }}
+}
+
+// From interior.rs
+#define periodicity_tolerance params.periodicity_tolerance
 
-    // Basically 1-dimensionalizes the image
-    highp uint global_count = gl_GlobalInvocationID.y * uint(local_dimensions.x) + gl_GlobalInvocationID.x;
-    highp uint global_y = global_count / uint(global_dimensions.x);
-    highp uint global_x = global_count - global_y * uint(global_dimensions.x);
+bool in_main_bulbs(Complex c) {
+    {{ float }} x = c.data.x - 0.25;
+    {{ float }} y_squared = c.data.y * c.data.y;
+    {{ float }} q = x * x + y_squared;
+    bool in_cardioid = q * (q + x) <= 0.25 * y_squared;
+    bool in_bulb = (c.data.x + 1.0) * (c.data.x + 1.0) + y_squared <= 0.0625;
+    return in_cardioid || in_bulb;
+}
+
+// Spreads samples over a pixel with the R2 sequence (the first sample is at the pixel's position)
+vec2 get_sample_offset(int index) {
+    return fract(0.5 + float(index) * vec2(0.7548776662466927, 0.5698402909980532)) - 0.5;
+}
 
-    highp vec2 cords = vec2(global_x, global_y) / vec2(global_dimensions);
+// Generates & colors a single sample, `cords` is the position of the sample in the chunk (from 0 to 1)
+vec4 render_sample({{ vec2 }} cords) {
 
-    /*
-    write_data(vec4(cords.x, cords.y, 0, 255));
-    return;
-    */
+    Complex c = Complex({{ vec2 }}(params.c_real, params.c_imaginary));
+    Complex z = Complex(
+        cords.x * {{ vec2 }}(params.factor_x, params.shear_y)
+        + cords.y * {{ vec2 }}(params.shear_x, params.factor_y)
+        + {{ vec2 }}(params.offset_x, params.offset_y)
+    );
 
-    Complex c = Complex(vec2({{ c_init }}));
-    Complex z = Complex(cords * vec2(params.factor_x, params.factor_y) + vec2(params.offset_x, params.offset_y));
+    // The derivatives of z & c with respect to the pixel position
+    Complex dz = number(1.0);
+    {{ float }} dc = {{ derivative_c }};
 
     {{ julia_changes }}
 
     float z_output = 0.0;
     bool travel_distance = {{ travel_distance }};
+    bool smooth_coloring = {{ smooth }};
+    float escape_limit = settings.escape_limit;
 
-    int maxi = {{ max_i }};
+    int maxi = int(settings.max_i);
     float added = 1.0 / maxi;
 
     vec3 res;
     Complex previous_z = z;
 
-    int i;
-    for (i = 0; i < maxi; i += 1) {
+    // There is no previous value before the first iteration so it starts infinitely far away
+    Complex last_z = Complex({{ vec2 }}(uintBitsToFloat(0x7F800000u)));
 
-        if (length(z.data) > 2.0) {
+    {%- if orbit_trap %}
+    // The closest the orbit has gotten to the orbit trap
+    float trap = uintBitsToFloat(0x7F800000u);
+    {%- endif %}
+
+    int i = 0;
+    {%- if bulb_check %}
+    // Pixels in the main cardioid or period 2 bulb never escape so they skip iterating
+    if (in_main_bulbs(c)) {
+        i = maxi;
+        z_output = maxi;
+    }
+    {%- endif %}
+    {%- if interior_checks %}
+    // Brent style cycle detection, z is compared against a value saved after 1, 2, 4, 8... iterations
+    Complex periodicity_z = z;
+    int periodicity_steps = 0;
+    int periodicity_length = 1;
+    {%- endif %}
+
+    for (; i < maxi; i += 1) {
+
+        if (escaped(z, last_z, escape_limit)) {
             break;
         }
+        last_z = z;
+        {%- if track_derivative %}
+        dz = derivative(z, c, dz, dc);
+        {%- endif %}
 
-        {{ formula }}
+        z = formula(z, c);
+        {%- if orbit_trap %}
+        trap = min(trap, trap_distance(z.data));
+        {%- endif %}
 
         if (travel_distance) {
-            z_output += distance(z.data, previous_z.data);
+            z_output += float(distance(z.data, previous_z.data));
             previous_z = z;
         } else {
             z_output += 1;
         }
+        {%- if interior_checks %}
+
+        // Orbits which fall into a cycle never escape
+        if (distance(z.data, periodicity_z.data) < periodicity_tolerance) {
+            i = maxi;
+            z_output = maxi;
+            break;
+        }
+        periodicity_steps += 1;
+        if (periodicity_steps == periodicity_length) {
+            periodicity_z = z;
+            periodicity_steps = 0;
+            periodicity_length *= 2;
+        }
+        {%- endif %}
+    }
+
+    // Finds the root the pixel converged to (for Newton type formulas)
+    int root = -1;
+    {%- if root_count > 0 %}
+    {{ vec2 }} roots[{{ root_count }}] = {{ vec2 }}[]({{ roots }});
+    if (i < maxi) {
+        root = 0;
+        for (int r = 1; r < {{ root_count }}; r += 1) {
+            if (distance(z.data, roots[r]) < distance(z.data, roots[root])) {
+                root = r;
+            }
+        }
+    }
+    {%- endif %}
+
+    // Distance estimate & normal (only for pixels which escaped past 1)
+    float distance_estimate = -1.0;
+    vec2 normal = vec2(0.0);
+    {%- if track_derivative %}
+    if (i < maxi && length(z.data) > 1.0) {
+        float z_length = float(length(z.data));
+        distance_estimate = 0.5 * z_length * log(z_length) / float(length(dz.data));
+        vec2 direction = vec2(divide(z, dz).data);
+        if (length(direction) > 0.0) {
+            normal = normalize(direction);
+        }
+    }
+    {%- endif %}
+    bool is_boundary = {{ distance_estimation }} && distance_estimate >= 0.0
+        && distance_estimate < settings.boundary_width * length(vec2(params.factor_x, params.shear_y)) / float(settings.width);
+
+    // Normalized iteration count for smooth coloring (only for pixels which escaped)
+    float z_value = z_output;
+    if (smooth_coloring && !travel_distance && z_output < maxi && length(z.data) > 1.0) {
+        z_value = z_output + 1.0 - log2(log(float(length(z.data))));
     }
 
     vec4 out_pixel;
 
     if (z_output == 0.0) {
-        out_pixel = vec4( {{ background }} );
-    } else if (z_output == maxi) {
-        out_pixel = vec4( {{ foreground }} );
+        out_pixel = settings.background;
+    } else if (z_output == maxi || is_boundary) {
+        out_pixel = settings.foreground;
     } else {
 
+        float color_value = z_value;
+        {%- if orbit_trap %}
+        // Orbit traps color by how close the orbit got to the trap (same as `Sample::get_color_value`)
+        color_value = trap;
+        {%- endif %}
+        {%- if histogram %}
+        // Spreads the distribution over one cycle of the color function (same as `Histogram::get_value`)
+        color_value = equalize(color_value) * settings.color_cycle;
+        {%- elif orbit_trap %}
+        // One radius of distance cycles through the colors once
+        color_value = trap * settings.color_cycle;
+        {%- endif %}
+
+        float hue;
+        if (root >= 0) {
+            hue = root_colors(color_value * settings.rate_of_color_change, root, {{ root_count }});
+        } else {
+            hue = colors(color_value * settings.rate_of_color_change);
+        }
+
+        {%- if palette %}
+        // Looks the color up in the palette the same way as `PaletteProfile::method`
+        uint index = uint(mod(hue / 360.0, 1.0) * {{ palette_size }}.0) % {{ palette_size }}u;
+        vec4 color = unpackUnorm4x8(palette[index]);
+        out_pixel = vec4(color.rgb * clamp(shadows(z_value, normal), 0.0, 1.0), color.a);
+        {%- else %}
         out_pixel = vec4(
             hsv_to_rgb(
                 vec3(
-                    mod(
-                        colors(
-                            z_output * {{ rate_of_color_change }}
-                        )
-                    / 360.0, 1.0),
+                    mod(hue / 360.0, 1.0),
                     1.0,
-                    shadows(z_output)
+                    shadows(z_value, normal)
                 )
             ),
             1.0
         );
+        {%- endif %}
+    }
+
+    return out_pixel;
+}
+
+vec3 srgb_to_linear(vec3 c) {
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), greaterThan(c, vec3(0.04045)));
+}
+
+vec3 linear_to_srgb(vec3 c) {
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, greaterThan(c, vec3(0.0031308)));
+}
+
+void main() {
+
+    highp ivec2 global_dimensions = ivec2(settings.width, params.amnt_of_lines); // global size
+
+    // The chunk image is as wide as the image so invocations map straight to pixels
+    highp uint global_x = gl_GlobalInvocationID.x;
+    highp uint global_y = gl_GlobalInvocationID.y;
+    if (global_x >= uint(global_dimensions.x) || global_y >= uint(global_dimensions.y)) {
+        return;
+    }
+
+    {{ vec2 }} pixel = {{ vec2 }}(global_x, global_y);
+    int samples = int(settings.samples);
+
+    if (samples == 1) {
+        write_data(render_sample(pixel / {{ vec2 }}(global_dimensions)));
+        return;
+    }
+
+    // Averages the samples in linear light (weighted by alpha so transparent samples don't darken the pixel)
+    vec4 total = vec4(0.0);
+    for (int s = 0; s < samples; s += 1) {
+        vec4 color = render_sample((pixel + {{ vec2 }}(get_sample_offset(s))) / {{ vec2 }}(global_dimensions));
+        total += vec4(srgb_to_linear(color.rgb) * color.a, color.a);
     }
 
-    write_data(out_pixel);
+    vec3 average = total.a > 0.0 ? total.rgb / total.a : vec3(0.0);
+    write_data(vec4(linear_to_srgb(average), total.a / float(samples)));
 }
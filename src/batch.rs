@@ -0,0 +1,278 @@
+/*
+  File for rendering batches of images from a manifest.
+
+  A manifest is a TOML or JSON file listing jobs. Each job holds the config fields (the same fields as
+  config files) which differ from the base fields shared by every job:
+    base = "render.toml"    Optional, a table of fields or the path of a config file
+    concurrency = 2         Optional, the amount of jobs rendered at the same time
+    [[jobs]]
+    filename = "overview"
+    [[jobs]]
+    filename = "detail"
+    max_i = 4096
+
+  Flags given on the command line apply to every job. GPU jobs share the device & compiled pipelines
+  so jobs with the same shader only get compiled once. Jobs which fail (or panic) are recorded in the
+  report without stopping the others.
+*/
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cli::{Args, BatchArgs};
+use crate::config_file::{merge, parse_config, read_file, write_file};
+use crate::cpu::get_thread_count;
+use crate::execution::{cpu_eval, gpu_eval_with_cache};
+use crate::gpu::GpuCache;
+use crate::overrides::apply_overrides;
+use crate::save::get_save_method;
+use crate::structs::Config;
+
+/// The jobs of a batch.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The fields shared by every job, either as a table or as the path of a config file.
+    #[serde(default)]
+    pub base: Option<Value>,
+    /// The amount of jobs rendered at the same time.
+    #[serde(default)]
+    pub concurrency: Option<u32>,
+    /// The fields of each job (on top of the base fields.)
+    pub jobs: Vec<Value>,
+}
+
+/// The result of a single job.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobReport {
+    /// The position of the job in the manifest (starting at 1.)
+    pub job: usize,
+    pub filename: Option<String>,
+    /// The file the image was saved to (if the save method saves to a file.)
+    pub output: Option<String>,
+    pub gpu: bool,
+    /// The time the job took in seconds.
+    pub seconds: f64,
+    /// Why the job failed (if it did.)
+    pub error: Option<String>,
+}
+
+/// The summary of a batch which gets written to the report file.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchReport {
+    pub manifest: String,
+    pub concurrency: u32,
+    /// The time the whole batch took in seconds.
+    pub seconds: f64,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub jobs: Vec<JobReport>,
+}
+
+/// Reads the manifest `filename`.
+pub fn read_manifest(filename: &str) -> Result<Manifest, Box<dyn Error>> {
+    let value = read_file(filename)?;
+    let manifest = serde_json::from_value(value).map_err(|e| format!("Invalid manifest `{}`: {}", filename, e))?;
+    return Ok(manifest);
+}
+
+/// Creates the config of each job in `manifest`.
+/// The fields of each job are set on top of `defaults` (the config made from the CLI arguments `args`) &
+/// the flags for which `is_set` returns true override them. Jobs which can't be generated get an error.
+pub fn get_configs(manifest: &Manifest, defaults: &Config, args: &Args, is_set: impl Fn(&str) -> bool) -> Result<Vec<Result<Config, String>>, Box<dyn Error>> {
+    let base = match &manifest.base {
+        Some(Value::String(filename)) => read_file(filename)?,
+        Some(value) => value.clone(),
+        None => Value::Object(Default::default()),
+    };
+
+    let mut configs = Vec::with_capacity(manifest.jobs.len());
+    let mut outputs: HashMap<String, usize> = HashMap::new();
+
+    for (index, job) in manifest.jobs.iter().enumerate() {
+        let mut fields = base.clone();
+        merge(&mut fields, job.clone());
+
+        let config = parse_config(fields, defaults)
+            .map_err(|e| format!("Invalid job: {}", e))
+            .and_then(|mut config| {
+                apply_overrides(&mut config, defaults, args, &is_set);
+                config.validate().map_err(|(_, e)| e)?;
+                config.update_math_frame();
+
+                // Jobs would overwrite each other's images
+                if let Some(output) = get_save_method(&config.save_method).get_output(&config) {
+                    if let Some(other) = outputs.insert(output.clone(), index) {
+                        return Err(format!("Job {} already saves to `{}`!", other + 1, output));
+                    }
+                }
+                return Ok(config);
+            });
+        configs.push(config);
+    }
+
+    return Ok(configs);
+}
+
+/// Renders every job in the manifest of `batch_args` & writes the summary to its report file.
+/// Has to be called outside of async code since jobs are generated on their own threads.
+pub fn run(batch_args: &BatchArgs, defaults: &Config, args: &Args, is_set: impl Fn(&str) -> bool) -> Result<BatchReport, Box<dyn Error>> {
+    let now = Instant::now();
+
+    let manifest = read_manifest(&batch_args.manifest)?;
+    let concurrency = batch_args.concurrency.or(manifest.concurrency).unwrap_or(1).max(1);
+    let configs: Vec<Result<Config, String>> = get_configs(&manifest, defaults, args, is_set)?
+        .into_iter()
+        // Jobs generated at the same time share the threads of the machine
+        .map(|config| config.map(|config| Config { threads: (get_thread_count(&config) / concurrency).max(1), ..config }))
+        .collect();
+
+    log::info!("Generating {} jobs ({} at a time)...", configs.len(), concurrency);
+
+    let handle = tokio::runtime::Handle::current();
+    let next_job = AtomicUsize::new(0);
+    let gpu_cache = Mutex::new(GpuCache::default());
+    let reports = Mutex::new(Vec::with_capacity(configs.len()));
+
+    // Each thread takes the next job until there are none left
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency {
+            scope.spawn(|| loop {
+                let index = next_job.fetch_add(1, Ordering::Relaxed);
+                let Some(config) = configs.get(index) else { break };
+
+                let job_now = Instant::now();
+                let result = match config {
+                    Ok(config) => {
+                        log::info!("[Job {}/{}] Generating `{}`...", index + 1, configs.len(), config.filename);
+                        // A panic only fails its own job so the others still run & the report gets written
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| match config.gpu {
+                            // GPU jobs share the device & pipelines so only one is generated at a time
+                            true => handle.block_on(gpu_eval_with_cache(config, &mut gpu_cache.lock())),
+                            false => handle.block_on(cpu_eval(config)),
+                        }));
+                        match result {
+                            Ok(result) => result.map_err(|e| e.to_string()),
+                            Err(payload) => Err(get_panic_message(payload)),
+                        }
+                    },
+                    Err(e) => Err(e.clone()),
+                };
+
+                let job = &manifest.jobs[index];
+                reports.lock().push(JobReport {
+                    job: index + 1,
+                    filename: match config {
+                        Ok(config) => Some(config.filename.clone()),
+                        Err(_) => job.get("filename").and_then(|v| v.as_str()).map(|v| v.to_string()),
+                    },
+                    output: config.as_ref().ok().and_then(|config| get_save_method(&config.save_method).get_output(config)),
+                    gpu: config.as_ref().is_ok_and(|config| config.gpu),
+                    seconds: job_now.elapsed().as_secs_f64(),
+                    error: result.err(),
+                });
+            });
+        }
+    });
+
+    let mut jobs = reports.into_inner();
+    jobs.sort_by_key(|v| v.job);
+    let failed = jobs.iter().filter(|v| v.error.is_some()).count();
+
+    let report = BatchReport {
+        manifest: batch_args.manifest.clone(),
+        concurrency,
+        seconds: now.elapsed().as_secs_f64(),
+        succeeded: jobs.len() - failed,
+        failed,
+        jobs,
+    };
+    write_file(&batch_args.report, &report)?;
+
+    return Ok(report);
+}
+
+/// Gets the message of a panic from its payload.
+fn get_panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (None, Some(message)) => message.clone(),
+        (None, None) => "unknown error".to_string(),
+    };
+    return format!("Panicked: {}", message);
+}
+
+/// Prints a table of the jobs in `report` (only the first line of each error, the report has the rest.)
+pub fn print_summary(report: &BatchReport) {
+    println!("{:>5}  {:>10}  Output", "Job", "Time");
+    for job in &report.jobs {
+        let output = job.output.clone().or(job.filename.clone()).unwrap_or_default();
+        match &job.error {
+            Some(e) => println!("{:>5}  {:>10}  {} ({})", job.job, "FAILED", output, e.lines().next().unwrap_or_default()),
+            None => println!("{:>5}  {:>9.2}s  {}", job.job, job.seconds, output),
+        }
+    }
+    println!("{} of {} jobs finished in {:.2}s", report.succeeded, report.jobs.len(), report.seconds);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn get_test_configs(manifest: &str) -> Vec<Result<Config, String>> {
+        let manifest: Manifest = toml::from_str(manifest).unwrap();
+        let args = Args::parse_from(["kyros", "-y"]);
+        return get_configs(&manifest, &Config::default(), &args, |_| false).unwrap();
+    }
+
+    #[test]
+    fn jobs_are_set_on_top_of_the_base() {
+        let configs = get_test_configs(r#"
+            base = { max_i = 500, save_method = "MOCK" }
+            jobs = [{ filename = "a" }, { filename = "b", max_i = 100 }]
+        "#);
+        let configs: Vec<Config> = configs.into_iter().map(Result::unwrap).collect();
+        assert_eq!((configs[0].max_i, configs[0].filename.as_str()), (500, "a"));
+        assert_eq!((configs[1].max_i, configs[1].filename.as_str()), (100, "b"));
+    }
+
+    #[test]
+    fn duplicate_outputs_fail() {
+        let configs = get_test_configs(r#"
+            base = { save_method = "PNG" }
+            jobs = [{ filename = "same" }, { filename = "other" }, { filename = "same" }]
+        "#);
+        assert!(configs[0].is_ok() && configs[1].is_ok());
+        assert_eq!(configs[2].as_ref().unwrap_err(), "Job 1 already saves to `same.png`!");
+
+        // Save methods which don't write files can't overwrite each other
+        let configs = get_test_configs(r#"
+            jobs = [{ save_method = "MOCK" }, { save_method = "MOCK" }]
+        "#);
+        assert!(configs.iter().all(Result::is_ok));
+    }
+
+    #[test]
+    fn invalid_jobs_fail_on_their_own() {
+        let configs = get_test_configs(r#"
+            jobs = [
+                { filename = "typo", max_iterations = 10 },
+                { filename = "constant", gen_formula = "NOVA", polynomial = [2.0] },
+                { filename = "nan", bailout = nan },
+                { filename = "conflict", gpu = true, deep_zoom = { center_real = "0", center_imaginary = "0", radius = 1.0 } },
+                { filename = "fine" },
+            ]
+        "#);
+        assert!(configs[0..4].iter().all(Result::is_err));
+        assert!(configs[4].is_ok());
+    }
+}
@@ -0,0 +1,247 @@
+/*
+  File for orbit density (Buddhabrot) rendering on the CPU
+
+  Instead of coloring each pixel by its own orbit, random values of `c` are sampled and every
+  point of their orbits adds a hit to the pixel it lands on. Each color channel has its own
+  iteration limit so different limits can be mixed into one image ("Nebulabrot".)
+*/
+
+use indicatif::{ProgressBar, ProgressStyle};
+use parking_lot::Mutex;
+
+use crate::cpu::get_thread_count;
+use crate::math::escape::{get_escape, Escape};
+use crate::math::formula::{get_formula, Formula};
+use crate::math::random::SplitMix64;
+use crate::structs::{Buddhabrot, Complex, Config};
+use crate::Level;
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+
+/// The amount of samples a thread takes before asking for more work.
+const SAMPLES_PER_BATCH: u64 = 1 << 14;
+
+/// The range sampled for `c` on both axes. Orbits of values outside of this escape straight away.
+const SAMPLE_RANGE: f64 = 2.0;
+
+/// Holds everything needed to accumulate orbit densities.
+pub struct BuddhabrotRenderer<'a> {
+    config: &'a Config,
+    settings: &'a Buddhabrot,
+    generator_function: Box<dyn Formula>,
+    escape_function: &'a dyn Escape,
+    start: Option<Complex<f64>>,
+}
+
+impl<'a> BuddhabrotRenderer<'a> {
+
+    /// Function for creating a new renderer from config.
+    /// Returns `None` if buddhabrot settings aren't set.
+    pub fn new(config: &'a Config) -> Option<Self> {
+        let settings = config.buddhabrot.as_ref()?;
+        let generator_function = get_formula(config);
+        return Some(Self {
+            config,
+            settings,
+            start: generator_function.get_start(),
+            generator_function,
+            escape_function: get_escape(config),
+        });
+    }
+
+    /// Renders the whole image into a buffer of RGB (or RGBA) values.
+    pub fn render(&self) -> Vec<u8> {
+        let density = self.get_density();
+        return self.tone_map(&density);
+    }
+
+    /// Samples `settings.orbits` orbits across `get_thread_count` threads.
+    /// Returns the amount of hits of each channel for each pixel (laid out as `[r, g, b, r, g, b, ...]`.)
+    /// Each batch of samples uses its own part of the random number stream so the result doesn't
+    /// depend on the amount of threads. Every thread adds its hits to the same buffer so memory
+    /// use doesn't grow with the amount of threads.
+    fn get_density(&self) -> Vec<u32> {
+
+        let pixel_count = self.config.size_x as usize * self.config.size_y as usize;
+        let batch_count = self.settings.orbits.div_ceil(SAMPLES_PER_BATCH);
+        let batches = Mutex::new(0..batch_count);
+        let density: Vec<AtomicU32> = (0..pixel_count * 3).map(|_| AtomicU32::new(0)).collect();
+
+        let progress = get_progress_bar(self.config, batch_count);
+
+        thread::scope(|scope| {
+            for _ in 0..get_thread_count(self.config) {
+                scope.spawn(|| {
+
+                    let mut orbit = Vec::new();
+
+                    loop {
+                        let Some(batch) = batches.lock().next() else { break };
+
+                        let first_sample = batch * SAMPLES_PER_BATCH;
+                        let samples = SAMPLES_PER_BATCH.min(self.settings.orbits - first_sample);
+
+                        // Each sample uses two random numbers
+                        let mut random = SplitMix64::new(self.settings.seed).skip(first_sample * 2);
+
+                        for _ in 0..samples {
+                            let c = Complex {
+                                real      : (random.next_f64() * 2.0 - 1.0) * SAMPLE_RANGE,
+                                imaginary : (random.next_f64() * 2.0 - 1.0) * SAMPLE_RANGE,
+                            };
+                            self.trace(c, &mut orbit, &density);
+                        }
+
+                        progress.inc(1);
+                    }
+                });
+            }
+        });
+
+        progress.finish();
+
+        return density.into_iter().map(AtomicU32::into_inner).collect();
+    }
+
+    /// Iterates the orbit of `c` & adds its points to each channel it counts towards.
+    /// `orbit` is reused between calls to avoid allocating.
+    fn trace(&self, c: Complex<f64>, orbit: &mut Vec<Complex<f64>>, density: &[AtomicU32]) {
+
+        let max_i = self.settings.channel_max_i.iter().copied().max().unwrap_or(0);
+        let limit = self.escape_function.get_limit(self.config);
+
+        let mut z = self.start.unwrap_or(c);
+        let mut previous_z = Complex { real: f64::INFINITY, imaginary: f64::INFINITY };
+        let mut escaped = false;
+
+        orbit.clear();
+        for _iteration in 0..max_i {
+            if self.escape_function.method_f64(z, previous_z, limit) {
+                escaped = true;
+                break;
+            }
+            orbit.push(z);
+            previous_z = z;
+            z = self.generator_function.method_f64(c, z);
+        }
+
+        for (channel, channel_max_i) in self.settings.channel_max_i.iter().enumerate() {
+            let channel_max_i = *channel_max_i as usize;
+
+            // Buddhabrots use the orbits which escape within the limit, anti-buddhabrots use the ones which don't
+            let escaped_in_time = escaped && orbit.len() <= channel_max_i;
+            if escaped_in_time == self.settings.anti {
+                continue;
+            }
+
+            for point in orbit.iter().take(channel_max_i) {
+                if let Some(index) = self.get_pixel_index(*point) {
+                    // Counts stop at the highest value instead of overflowing
+                    let _ = density[index * 3 + channel].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| v.checked_add(1));
+                }
+            }
+        }
+    }
+
+    /// Gets the index of the pixel a point in math space lands on (if it is in the image.)
+    fn get_pixel_index(&self, point: Complex<f64>) -> Option<usize> {
+        let (x, y) = self.config.math_frame.get_pixel(point);
+        let (x, y) = (x.round(), y.round());
+
+        if x < 0.0 || y < 0.0 || x >= self.config.size_x as f64 || y >= self.config.size_y as f64 {
+            return None;
+        }
+        return Some(y as usize * self.config.size_x as usize + x as usize);
+    }
+
+    /// Converts hit counts into colors.
+    /// Each channel is scaled by its highest count & then raised to the power of `settings.gamma`
+    /// (values under 1 brighten the faint parts of the image.)
+    fn tone_map(&self, density: &[u32]) -> Vec<u8> {
+
+        let mut channel_max = [0u32; 3];
+        for pixel in density.chunks(3) {
+            for channel in 0..3 {
+                channel_max[channel] = channel_max[channel].max(pixel[channel]);
+            }
+        }
+
+        let bytes_per_pixel = 3 + self.config.rgba as usize;
+        let mut img = vec![255u8; density.len() / 3 * bytes_per_pixel];
+
+        for (pixel, hits) in img.chunks_mut(bytes_per_pixel).zip(density.chunks(3)) {
+            for channel in 0..3 {
+                let value = match channel_max[channel] {
+                    0 => 0.0,
+                    max => (hits[channel] as f64 / max as f64).powf(self.settings.gamma),
+                };
+                pixel[channel] = (value * 255.0).round() as u8;
+            }
+        }
+
+        return img;
+    }
+
+}
+
+/// Function for getting the progress bar used for orbit sampling.
+/// The bar is hidden unless logs are set to at least `INFO`.
+fn get_progress_bar(config: &Config, len: u64) -> ProgressBar {
+    if config.logs < Level::Info {
+        return ProgressBar::hidden();
+    }
+    return ProgressBar::new(len).with_style(
+        ProgressStyle::with_template("CPU Orbits   [Batch {pos:>9.yellow}/{len:.red}] {bar:80.green} %{percent:3}").unwrap()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::MathFrame;
+
+    fn config(threads: u32) -> Config {
+        return Config {
+            size_x: 16,
+            size_y: 16,
+            threads,
+            // Covers -2..2 on both axes
+            math_frame: MathFrame { factor_x: 0.25, factor_y: 0.25, shear_x: 0.0, shear_y: 0.0, offset_x: -2.0, offset_y: -2.0 },
+            buddhabrot: Some(Buddhabrot {
+                orbits: SAMPLES_PER_BATCH * 3 + 100,
+                channel_max_i: [10, 50, 200],
+                anti: false,
+                seed: 7,
+                gamma: 1.0,
+            }),
+            ..Config::default()
+        };
+    }
+
+    #[test]
+    fn pixel_index() {
+        let config = config(1);
+        let renderer = BuddhabrotRenderer::new(&config).unwrap();
+        assert_eq!(renderer.get_pixel_index(Complex { real: -2.0, imaginary: -2.0 }), Some(0));
+        assert_eq!(renderer.get_pixel_index(Complex { real: 0.0, imaginary: 0.25 }), Some(9 * 16 + 8));
+        assert_eq!(renderer.get_pixel_index(Complex { real: 2.0, imaginary: 0.0 }), None);
+        assert_eq!(renderer.get_pixel_index(Complex { real: 0.0, imaginary: -2.2 }), None);
+    }
+
+    #[test]
+    fn density_does_not_depend_on_threads() {
+        let one = config(1);
+        let four = config(4);
+        let density = BuddhabrotRenderer::new(&one).unwrap().get_density();
+        assert_eq!(density, BuddhabrotRenderer::new(&four).unwrap().get_density());
+        assert!(density.iter().any(|v| *v > 0));
+    }
+
+    #[test]
+    fn tone_map_scales_each_channel() {
+        let config = Config { size_x: 2, size_y: 1, rgba: false, ..config(1) };
+        let renderer = BuddhabrotRenderer::new(&config).unwrap();
+        assert_eq!(renderer.tone_map(&[4, 0, 1, 2, 0, 2]), vec![255, 0, 128, 128, 0, 255]);
+    }
+}
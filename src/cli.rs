@@ -7,9 +7,12 @@ Author : Mark T
   File for storing CLI Configuration
 */
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use log::LevelFilter;
 
+use crate::colors::palette::GradientSpace;
+use crate::structs::{Precision, Strategy, TrapShape};
+
 static ABOUT_CLI_ARGS: &str = "
  ~ Kyros
 A CLI tool for generating fractal images.
@@ -33,9 +36,12 @@ kyros --pixels 512       \\
       -y
 
 Getting more help:
-Potential values for the formula, color and shadow flags can be retreived by passing an invalid values (such as 'HELP') to them.
+Potential values for the formula, color, shadow and escape flags can be retreived by passing an invalid values (such as 'HELP') to them.
 ";
 
+/// The flags which place the image with factors & offsets, these can't be mixed with the view flags.
+const VIEW_CONFLICTS: [&str; 5] = ["factor_x", "factor_y", "offset_x", "offset_y", "stretch"];
+
 #[macro_export]
 macro_rules! clap_enum_variants {
     ($e: ty) => {{
@@ -57,18 +63,57 @@ macro_rules! clap_enum_variants {
 #[command(version)]
 pub struct Args {
 
-    /// The amount of pixels to generate
+    /// The width & height of the image in pixels (use --width, --height or --size for other shapes)
     #[arg(short, long, default_value_t = 256, value_name="INT")]
     pub pixels: u32,
 
+    /// Sets the width of the image in pixels (defaults to --pixels)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), value_name="INT", conflicts_with="size")]
+    pub width: Option<u32>,
+
+    /// Sets the height of the image in pixels (defaults to --pixels)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), value_name="INT", conflicts_with="size")]
+    pub height: Option<u32>,
+
+    /// Sets the width & height of the image in pixels. Ex: '3840x2160'
+    #[arg(long, value_name="WIDTHxHEIGHT", value_parser=parse_size)]
+    pub size: Option<(u32, u32)>,
+
+    /// Stretches the area set by the factor & offset flags over the image. By default the shorter
+    /// side of the area gets extended (around its center) so pixels stay square.
+    #[arg(long, default_value_t=false, value_name="BOOL")]
+    pub stretch: bool,
+
     /// The amount of iterations to run per pixel
     #[arg(short, long, default_value_t = 1024, value_name="INT")]
     pub iterations: u64,
 
+    /// The amount of samples per pixel, more samples smooth out jagged edges (anti-aliasing)
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), value_name="INT")]
+    pub samples: u32,
+
+    /// Re-renders pixels which differ strongly from their neighbours with this many samples
+    /// (adaptive anti-aliasing.) Only works on the CPU.
+    #[arg(long, default_value_t = 0, value_name="INT", conflicts_with="gpu")]
+    pub adaptive_samples: u32,
+
+    /// Sets how much a pixel's iteration value has to differ from a neighbour's to be refined (used with --adaptive-samples)
+    #[arg(long, default_value_t = 2.0, value_name="FLOAT")]
+    pub adaptive_threshold: f64,
+
     /// The generation function to use
-    #[arg(short, long, default_value_t=("SD".to_string()), value_name="STR", long_help="Sets the generation function to use. \nSet this value to 'HELP' for more information.")] // The Compiler lies, parentheses are needed
+    #[arg(short, long, default_value_t=("SD".to_string()), value_name="STR", long_help="Sets the generation function to use. \nThis can also be an expression of z & c such as 'z^3 + c*sin(z)'. \nSet this value to 'HELP' for more information.")] // The Compiler lies, parentheses are needed
     pub formula: String,
 
+    /// Sets the power used by the MULTI formula (z = z^p + c), can be negative or fractional
+    #[arg(long, default_value_t=2.0, allow_hyphen_values(true), value_name="FLOAT")]
+    pub power: f64,
+
+    /// Sets the coefficients of the polynomial used by the NEWTON & NOVA formulas (highest degree first)
+    /// Ex: '1,0,0,-1' is z^3 - 1
+    #[arg(long, default_values_t=[1.0, 0.0, 0.0, -1.0], value_delimiter=',', allow_hyphen_values(true), value_name="FLOATS")]
+    pub polynomial: Vec<f64>,
+
     /// Specifies color function to use
     #[arg(long, default_value_t=("ROTATIONAL".to_string()), value_name="STR")]
     pub color: String,
@@ -81,6 +126,17 @@ pub struct Args {
     #[arg(long, default_value_t=("NONE".to_string()), value_name="STR")]
     pub shadow: String,
 
+    /// Colors pixels with a gradient of CSS colors instead of rotating the hue.
+    /// Ex: '#000 0, red 25%, rgb(255, 200, 0) 0.5, white 75%'. Colors without a position are spread evenly.
+    /// This can also be the path of a Fractint (.map), GIMP (.gpl) or Ultra Fractal (.ugr) palette file.
+    /// The palette is cycled through once every 360 / rate-of-color-change iterations.
+    #[arg(long, value_name="STOPS")]
+    pub palette: Option<String>,
+
+    /// Sets the color space palette colors are interpolated in (used with --palette)
+    #[arg(long, value_enum, default_value_t=GradientSpace::Oklab, value_name="SPACE")]
+    pub palette_space: GradientSpace,
+
     /// Specifies which color to use for the background
     #[arg(long, default_value_t=("rgba(255, 255, 255, 0)".to_string()), value_name="COLOR")]
     pub background: String,
@@ -129,14 +185,148 @@ pub struct Args {
     #[arg(long, default_value_t=-2.0, allow_hyphen_values(true), value_name="FLOAT")]
     pub offset_y: f64,
 
+    /// Uses perturbation theory for deep zooms past what f64 allows. The image is placed with the
+    /// center & radius flags instead of the factor & offset flags. Only works with the 'SD'
+    /// formula on the CPU.
+    #[arg(long, default_value_t=false, value_name="BOOL", conflicts_with_all=["gpu", "julia"])]
+    pub deep_zoom: bool,
+
+    /// Renders the density of escaping orbits (Buddhabrot) instead of coloring each pixel by its
+    /// own orbit. Only works on the CPU.
+    #[arg(long, default_value_t=false, value_name="BOOL", conflicts_with_all=["gpu", "julia", "deep_zoom"])]
+    pub buddhabrot: bool,
+
+    /// Renders the density of orbits which don't escape (Anti-Buddhabrot.)
+    #[arg(long, default_value_t=false, value_name="BOOL", conflicts_with_all=["gpu", "julia", "deep_zoom"])]
+    pub anti_buddhabrot: bool,
+
+    /// Sets the amount of random orbits to sample (used with --buddhabrot)
+    #[arg(long, default_value_t=1_000_000, value_name="INT")]
+    pub orbits: u64,
+
+    /// Sets separate iteration limits for the red, green & blue channels (used with --buddhabrot)
+    /// Ex: '5000,500,50'. All channels use --iterations if this isn't set.
+    #[arg(long, num_args=3, value_delimiter=',', value_name="INTS")]
+    pub nebula: Option<Vec<u64>>,
+
+    /// Sets the seed for random sampling (used with --buddhabrot)
+    #[arg(long, default_value_t=0, value_name="INT")]
+    pub seed: u64,
+
+    /// Sets the exponent applied to orbit densities, values under 1 brighten faint orbits (used with --buddhabrot)
+    #[arg(long, default_value_t=0.5, value_name="FLOAT")]
+    pub density_gamma: f64,
+
+    /// Sets the real value of the center of the image as a decimal string [default: -0.5]
+    /// Places the image with the view flags (center, radius, zoom & rotation) instead of the factor & offset flags.
+    #[arg(long, allow_hyphen_values(true), value_name="DECIMAL", conflicts_with_all=VIEW_CONFLICTS)]
+    pub center_real: Option<String>,
+
+    /// Sets the imaginary value of the center of the image as a decimal string [default: 0]
+    #[arg(long, allow_hyphen_values(true), value_name="DECIMAL", conflicts_with_all=VIEW_CONFLICTS)]
+    pub center_imaginary: Option<String>,
+
+    /// Sets the distance from the center to the top & bottom edges of the image [default: 2]
+    #[arg(long, value_name="FLOAT", value_parser=parse_positive, conflicts_with_all=VIEW_CONFLICTS)]
+    pub radius: Option<f64>,
+
+    /// Sets the magnification of the image, the same as a radius of `2 / zoom`
+    #[arg(long, value_name="FLOAT", value_parser=parse_positive, conflicts_with_all=VIEW_CONFLICTS, conflicts_with="radius")]
+    pub zoom: Option<f64>,
+
+    /// Rotates the image counter clockwise around its center by this many degrees [default: 0]
+    #[arg(long, allow_hyphen_values(true), value_name="DEGREES", conflicts_with_all=VIEW_CONFLICTS)]
+    pub rotation: Option<f64>,
+
     /// Uses Travel Distance to color pixels
     #[arg(long, default_value_t=false, value_name="BOOL")]
     pub travel_distance: bool,
 
+    /// Uses smooth coloring (normalized iteration count) instead of the integer iteration count.
+    /// Larger bailouts make the result smoother.
+    #[arg(long, default_value_t=false, value_name="BOOL")]
+    pub smooth: bool,
+
+    /// Colors pixels by how many pixels have a lower iteration value (histogram coloring) so colors are
+    /// spread evenly over the image. The colors cycle once over the whole image.
+    #[arg(long, default_value_t=false, value_name="BOOL")]
+    pub histogram: bool,
+
+    /// Colors pixels by how close their orbit gets to a shape (orbit trap) instead of their iteration count.
+    /// Distances of one trap radius cycle through the colors once.
+    #[arg(long, value_enum, value_name="SHAPE", conflicts_with_all=["buddhabrot", "anti_buddhabrot"])]
+    pub orbit_trap: Option<TrapShape>,
+
+    /// Sets the real value of the center of the orbit trap (used with --orbit-trap)
+    #[arg(long, default_value_t=0.0, allow_hyphen_values(true), value_name="FLOAT")]
+    pub trap_real: f64,
+
+    /// Sets the imaginary value of the center of the orbit trap (used with --orbit-trap)
+    #[arg(long, default_value_t=0.0, allow_hyphen_values(true), value_name="FLOAT")]
+    pub trap_imaginary: f64,
+
+    /// Sets the angle (in degrees) of line, cross & image orbit traps (used with --orbit-trap)
+    #[arg(long, default_value_t=0.0, allow_hyphen_values(true), value_name="FLOAT")]
+    pub trap_angle: f64,
+
+    /// Sets the radius of circle orbit traps & half the width of image orbit traps. Distances to the
+    /// trap are measured in units of this (used with --orbit-trap)
+    #[arg(long, default_value_t=1.0, value_name="FLOAT")]
+    pub trap_radius: f64,
+
+    /// Sets the image used by image orbit traps, orbits landing on bright parts of the image are trapped (used with '--orbit-trap image')
+    #[arg(long, value_name="FILE")]
+    pub trap_image: Option<String>,
+
+    /// Sets the bailout radius, pixels escape once the absolute value of z is larger than this
+    #[arg(long, default_value_t=2.0, value_name="FLOAT")]
+    pub bailout: f64,
+
+    /// Sets the condition for when a pixel stops iterating (uses the formula's default if not set).
+    /// Set this value to 'HELP' for more information.
+    #[arg(long, value_name="STR")]
+    pub escape: Option<String>,
+
+    /// Sets how little z has to move between iterations to count as converged (used with '--escape CONVERGE')
+    #[arg(long, default_value_t=1e-6, value_name="FLOAT")]
+    pub tolerance: f64,
+
+    /// Uses the foreground color for pixels close to the set (using distance estimation) which
+    /// shows thin parts of the boundary clearly.
+    #[arg(long, default_value_t=false, value_name="BOOL")]
+    pub distance_estimation: bool,
+
+    /// Sets how close (in pixels) a pixel has to be to the set to be drawn as the boundary (used with --distance-estimation)
+    #[arg(long, default_value_t=1.0, value_name="FLOAT")]
+    pub boundary_width: f64,
+
+    /// Sets the angle (in degrees) the light comes from for the NORMAL shadow
+    #[arg(long, default_value_t=45.0, allow_hyphen_values(true), value_name="FLOAT")]
+    pub light_angle: f64,
+
+    /// Sets the height of the light for the NORMAL shadow, higher values give softer shading
+    #[arg(long, default_value_t=1.5, value_name="FLOAT")]
+    pub light_height: f64,
+
     /// Uses GPU for Image Generation
     #[arg(long, default_value_t=false, value_name="BOOL")]
     pub gpu: bool,
 
+    /// Sets the float type used for generation math. `f64` allows for deeper zooms but is slower
+    /// and requires `shader_float64` support when using the GPU.
+    #[arg(long, value_enum, default_value_t=Precision::F32, value_name="PRECISION")]
+    pub precision: Precision,
+
+    /// Sets the way the CPU decides which pixels to generate. `subdivide` skips the inside of
+    /// tiles whose border is all the same, which is much faster for images with large flat areas.
+    #[arg(long, value_enum, default_value_t=Strategy::Pixels, value_name="STRATEGY", conflicts_with="gpu")]
+    pub strategy: Strategy,
+
+    /// Also checks the lines through the middle of tiles before filling them in (used with --strategy subdivide)
+    /// This catches small features which don't touch a tile's border at the cost of some speed.
+    #[arg(long, default_value_t=false, value_name="BOOL")]
+    pub subdivision_guard: bool,
+
     /// The size of the generated PNG chunks. If the intent is to only generate one chunk, this
     /// should not be set.
     #[arg(long, value_name="Option<INT>")]
@@ -150,11 +340,35 @@ pub struct Args {
     #[arg(long, default_value_t=4, value_name="INT")]
     pub compression_threads: u32,
 
+    /// Sets the amount of threads to use for CPU generation. 0 uses the amount of available cores
+    /// (of the machine generating the image.)
+    #[arg(long, default_value_t=0, value_name="INT")]
+    pub threads: u32,
+
 
     /// Tries to read the config from a generated file.
     #[arg(short, long, value_name="BOOL")]
     pub read_config: Option<String>,
 
+    /// Recolors a file saved with the RAW save method using the color flags (instead of generating an image.)
+    /// The size, formula & other generation settings come from the file.
+    #[arg(long, value_name="FILE", conflicts_with="read_config")]
+    pub recolor: Option<String>,
+
+    /// Regenerates an image from the config saved in it (PNG or RAW files.) Other flags override
+    /// the saved settings, Ex: a larger size renders the same area in more detail.
+    #[arg(long, value_name="FILE", conflicts_with_all=["read_config", "recolor"])]
+    pub reproduce: Option<String>,
+
+    /// Loads the settings from a TOML or JSON config file (with the same fields as the config saved in
+    /// images.) Flags given on the command line override the values in the file.
+    #[arg(long, value_name="FILE", conflicts_with="reproduce")]
+    pub config: Option<String>,
+
+    /// Writes the settings which would be used to a TOML or JSON config file instead of generating an image.
+    #[arg(long, value_name="FILE", conflicts_with_all=["read_config", "recolor"])]
+    pub dump_config: Option<String>,
+
     /// Sets the verbosity of logs
     #[arg(long, default_value_t=default_level_filter(), value_name="LevelFilter", value_parser=clap_enum_variants!(LevelFilter))]
     pub logs: LevelFilter,
@@ -162,8 +376,61 @@ pub struct Args {
     /// Confirm image generation
     #[arg(short, long, required(true))]
     pub y_confirm: bool,
+
+    /// Runs a subcommand instead of generating a single image
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands which replace generating a single image.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Renders every job listed in a manifest (TOML or JSON.) Flags given before `batch` apply to every job.
+    Batch(BatchArgs),
+}
+
+/// Arguments for the `batch` subcommand.
+#[derive(clap::Args, Debug, Clone)]
+pub struct BatchArgs {
+    /// The manifest listing the jobs to render
+    #[arg(value_name="MANIFEST")]
+    pub manifest: String,
+
+    /// Sets the amount of jobs rendered at the same time (defaults to the manifest's `concurrency` or 1)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), value_name="INT")]
+    pub concurrency: Option<u32>,
+
+    /// Sets the file the summary of the batch is written to (TOML or JSON)
+    #[arg(long, default_value_t=("batch_report.json".to_string()), value_name="FILE")]
+    pub report: String,
 }
 
 pub fn default_level_filter() -> LevelFilter {
     LevelFilter::Off
 }
+
+/// Parses a number which has to be larger than 0.
+pub fn parse_positive(value: &str) -> Result<f64, String> {
+    return match value.trim().parse::<f64>() {
+        Ok(v) if v > 0.0 && v.is_finite() => Ok(v),
+        _ => Err(format!("Expected a number larger than 0 but found '{}'", value)),
+    };
+}
+
+/// Parses an image size like '3840x2160' into its width & height.
+pub fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or(format!("Expected a size like '3840x2160' but found '{}'", value))?;
+    let parse = |v: &str| match v.trim().parse::<u32>() {
+        Ok(v) if v > 0 => Ok(v),
+        _ => Err(format!("Invalid size '{}' (sizes have to be whole numbers larger than 0)", v.trim())),
+    };
+    return Ok((parse(width)?, parse(height)?));
+}
+
+pub fn default_thread_count() -> u32 {
+    std::thread::available_parallelism()
+        .map(|v| v.get() as u32)
+        .unwrap_or(1)
+}
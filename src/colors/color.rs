@@ -6,11 +6,19 @@ use clap::CommandFactory;
 use crate::structs::Config;
 use crate::cli::Args;
 
-pub trait Colors {
+pub trait Colors: Sync {
     fn get_alias(&self) -> String;
     fn get_description(&self) -> String;
     fn method(&self, n: f64, config: &Config) -> f64;
     fn gpu_method(&self) -> String;
+    /// Gets the hue of a pixel which converged to the root with index `root` (out of `roots` roots.)
+    /// By default each root gets an evenly spaced hue which `method` is added on top of.
+    fn root_method(&self, n: f64, root: usize, roots: usize, config: &Config) -> f64 {
+        360.0 * root as f64 / roots as f64 + self.method(n, config)
+    }
+    fn gpu_root_method(&self) -> String {
+        "return 360.0 * float(root) / float(roots) + colors(n);".into()
+    }
 }
 
 pub struct ROTATIONAL {}
@@ -46,18 +54,35 @@ impl Colors for SINUSOIDAL {
     }
 }
 
+pub struct BASIN {}
+impl Colors for BASIN {
+    fn get_alias(&self) -> String { "BASIN".into() }
+    fn get_description(&self) -> String { "Only colors by the root a pixel converges to (for Newton type formulas)".into() }
+    fn method(&self, _n: f64, _config: &Config) -> f64 { 0.0 }
+    fn gpu_method(&self) -> String {
+        "return 0.0;".into()
+    }
+}
+
 /// Function for getting the color formula from config
+/// Exits with the list of colors if it can't be found.
 pub fn get_color(color: &str) -> &dyn Colors {
+    return try_get_color(color).unwrap_or_else(|e| Args::command().error(ErrorKind::InvalidValue, e).exit());
+}
+
+/// Same as `get_color` but returns the error message instead of exiting.
+pub fn try_get_color(color: &str) -> Result<&'static dyn Colors, String> {
 
-    let colors: Vec<&dyn Colors> = vec![
+    let colors: Vec<&'static dyn Colors> = vec![
         &ROTATIONAL {},
         &SINUSOIDAL {},
+        &BASIN      {},
     ];
 
     // Tries to find function in colors array
     for method in colors.clone() {
         if method.get_alias() == color.to_string() {
-            return method;
+            return Ok(method);
         }
     }
 
@@ -67,9 +92,6 @@ pub fn get_color(color: &str) -> &dyn Colors {
         .collect::<Vec<String>>()
         .join("\n");
 
-    // If not found throw error
-    Args::command().error(
-        ErrorKind::InvalidValue,
-        format!("Color method '{}' not found!\n\nAllowed Colors:\n{}", color, color_string)
-    ).exit();
+    // If not found return error
+    return Err(format!("Color method '{}' not found!\n\nAllowed Colors:\n{}", color, color_string));
 }
@@ -0,0 +1,100 @@
+/*
+  File for histogram coloring.
+  The distribution of the color values of an image is stored as a table of quantiles which pixels get
+  mapped through before calling the color function. This spreads the colors evenly over the pixels
+  instead of over the iteration counts. The GPU gets the same table so the output matches.
+*/
+
+/// The amount of quantiles the distribution is stored as.
+pub const HISTOGRAM_SIZE: usize = 256;
+
+/// The largest width or height of the preview used to collect the distribution of an image.
+/// Images smaller than this use every pixel.
+pub const PREVIEW_SIZE: u32 = 512;
+
+/// The distribution of the color values of the pixels which escaped.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    /// The value below which `i / (HISTOGRAM_SIZE - 1)` of the pixels are (sorted)
+    quantiles: Vec<f64>,
+}
+
+impl Histogram {
+
+    /// Creates the distribution of `values`.
+    pub fn new(mut values: Vec<f64>) -> Self {
+        values.retain(|v| v.is_finite());
+        values.sort_by(f64::total_cmp);
+
+        // Images without escaped pixels still get a full table (the GPU needs `HISTOGRAM_SIZE` values)
+        if values.is_empty() {
+            return Self { quantiles: vec![0.0; HISTOGRAM_SIZE] };
+        }
+
+        let last = (values.len() - 1) as f64;
+        let quantiles = (0..HISTOGRAM_SIZE)
+            .map(|i| values[(i as f64 * last / (HISTOGRAM_SIZE - 1) as f64).round() as usize])
+            .collect();
+        return Self { quantiles };
+    }
+
+    /// Gets the fraction (from 0 to 1) of pixels with a color value below `value` (the cumulative distribution.)
+    /// Values between two quantiles are interpolated so smooth coloring stays smooth.
+    /// The GLSL `equalize` function does the same.
+    pub fn equalize(&self, value: f64) -> f64 {
+        let last = self.quantiles.len() - 1;
+        let index = self.quantiles.partition_point(|v| *v <= value);
+        if index == 0 {
+            return 0.0;
+        }
+        if index > last {
+            return 1.0;
+        }
+
+        let (low, high) = (self.quantiles[index - 1], self.quantiles[index]);
+        return ((index - 1) as f64 + (value - low) / (high - low)) / last as f64;
+    }
+
+    /// Gets the value passed to the color function for a pixel with the color value `value`.
+    /// The distribution is spread over one cycle of the color function (`360 / rate_of_color_change`.)
+    pub fn get_value(&self, value: f64, rate_of_color_change: f64) -> f64 {
+        return self.equalize(value) * 360.0 / rate_of_color_change;
+    }
+
+    /// Gets the quantiles of the distribution.
+    pub fn get_quantiles(&self) -> &[f64] {
+        return &self.quantiles;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_input_has_every_quantile() {
+        let histogram = Histogram::new(vec![]);
+        assert_eq!(histogram.get_quantiles().len(), HISTOGRAM_SIZE);
+        assert_eq!(histogram.equalize(-1.0), 0.0);
+        assert_eq!(histogram.equalize(10.0), 1.0);
+
+        let histogram = Histogram::new(vec![f64::NAN, f64::INFINITY]);
+        assert_eq!(histogram.get_quantiles().len(), HISTOGRAM_SIZE);
+    }
+
+    #[test]
+    fn equalize_spreads_values() {
+        let histogram = Histogram::new((0..1000).map(|v| (v as f64).powi(2)).collect());
+        assert_eq!(histogram.get_quantiles().len(), HISTOGRAM_SIZE);
+        assert!((histogram.equalize(500.0_f64.powi(2)) - 0.5).abs() < 0.01);
+        assert!(histogram.equalize(10.0) < histogram.equalize(20.0));
+    }
+
+    #[test]
+    fn values_span_one_color_cycle() {
+        let histogram = Histogram::new((0..100).map(|v| v as f64).collect());
+        assert_eq!(histogram.get_value(-1.0, 2.0), 0.0);
+        assert_eq!(histogram.get_value(1000.0, 2.0), 180.0);
+    }
+}
@@ -1,3 +1,8 @@
 pub mod color;
 pub mod shadows;
 pub mod profiles;
+pub mod painter;
+pub mod histogram;
+pub mod palette;
+pub mod palette_file;
+pub mod space;
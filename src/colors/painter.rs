@@ -0,0 +1,81 @@
+/*
+  File for turning generated samples into colors.
+  This is kept apart from generation so samples saved with the RAW save method can be recolored.
+*/
+
+use crate::colors::color::{get_color, Colors};
+use crate::colors::histogram::Histogram;
+use crate::colors::profiles::{get_profile, ColorProfile};
+use crate::colors::shadows::{get_shadow, Shadows};
+use crate::structs::{Complex, Config, Sample};
+
+/// Holds everything needed to color samples.
+pub struct Painter<'a> {
+    config: &'a Config,
+    color_function: &'a dyn Colors,
+    shadow_function: &'a dyn Shadows,
+    color_profile: Box<dyn ColorProfile>,
+    /// The amount of roots pixels can converge to (for Newton type formulas)
+    root_count: usize,
+    /// The distribution color values get mapped through (for histogram coloring)
+    histogram: Option<Histogram>,
+}
+
+impl<'a> Painter<'a> {
+
+    /// Function for creating a new painter from config.
+    pub fn new(config: &'a Config, root_count: usize) -> Self {
+        return Self {
+            config,
+            color_function: get_color(config.color_formula.as_str()),
+            shadow_function: get_shadow(config.shadow_formula.as_str()),
+            color_profile: get_profile(config),
+            root_count,
+            histogram: None,
+        };
+    }
+
+    /// Sets the distribution color values get mapped through before calling the color function.
+    pub fn set_histogram(&mut self, histogram: Histogram) {
+        self.histogram = Some(histogram);
+    }
+
+    /// Gets the distribution color values get mapped through (if histogram coloring is used.)
+    pub fn get_histogram(&self) -> Option<&Histogram> {
+        return self.histogram.as_ref();
+    }
+
+    /// Gets the color of a generated pixel.
+    pub fn color(&self, sample: &Sample) -> Vec<u8> {
+        let boundary_distance = self.config.boundary_width * self.config.math_frame.get_pixel_size();
+        return match sample.z_output {
+            x if x == 0.0 => self.color_profile.get_background().to_owned(),
+            _ if sample.is_inside => self.color_profile.get_foreground().to_owned(),
+            // Pixels close enough to the set are drawn as its boundary
+            _ if self.config.distance_estimation && sample.distance.is_some_and(|v| v < boundary_distance) => {
+                self.color_profile.get_foreground().to_owned()
+            },
+            _ => {
+                let value = sample.get_value(self.config);
+                let color_value = match (&self.histogram, sample.trap) {
+                    (Some(histogram), _) => histogram.get_value(sample.get_color_value(self.config), self.config.rate_of_color_change),
+                    // Trap distances are in units of the trap's radius, which cycles through the colors once
+                    (None, Some(trap)) => trap * 360.0 / self.config.rate_of_color_change,
+                    (None, None) => value,
+                };
+                let hue = match sample.root {
+                    Some(root) => self.color_function.root_method(color_value, root, self.root_count, self.config),
+                    None => self.color_function.method(color_value, self.config),
+                };
+                // Pixels without a normal use a zero normal like they do on the GPU
+                let normal = sample.normal.unwrap_or(Complex { real: 0.0, imaginary: 0.0 });
+                let lightness = self.shadow_function.normal_method(value, normal, self.config);
+                self.color_profile.method(
+                    hue.rem_euclid(360.0),
+                    lightness.rem_euclid(360.0),
+                )
+            },
+        };
+    }
+
+}
@@ -0,0 +1,258 @@
+/*
+  File for gradient palettes.
+  A palette is a list of colors with positions which get interpolated into a table. Both the CPU
+  & GPU look colors up in the same table so they give the same output.
+*/
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::colors::palette_file;
+use crate::colors::space::{linear_to_oklab, linear_to_srgb, oklab_to_linear, srgb_to_linear};
+
+/// The amount of colors in a palette's table.
+pub const PALETTE_SIZE: usize = 1024;
+
+/// The color space palette colors are interpolated in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientSpace {
+    /// Interpolates the sRGB encoded values (how most image editors blend colors.)
+    Srgb,
+    /// Interpolates in linear light, which keeps blends between bright colors bright.
+    Linear,
+    /// Interpolates in OKLab, a perceptually uniform color space which gives the most even gradients.
+    #[default]
+    Oklab,
+}
+
+/// A color of a palette & where it is in the palette (from 0 to 1.)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PaletteStop {
+    pub color: csscolorparser::Color,
+    pub position: f64,
+}
+
+/// A gradient of colors which is cycled through by the color value of each pixel.
+/// The gradient wraps around from the last stop back to the first so cycling it doesn't leave seams.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Palette {
+    pub stops: Vec<PaletteStop>,
+    pub space: GradientSpace,
+}
+
+impl Palette {
+
+    /// Parses a list of comma separated CSS colors, each optionally followed by its position
+    /// (from 0 to 1 or as a percentage.)
+    /// Ex: `#000 0, red 25%, rgb(255, 200, 0) 0.5, white 75%`
+    /// Stops without a position are spread evenly.
+    pub fn parse(value: &str, space: GradientSpace) -> Result<Self, String> {
+
+        let mut stops = Vec::new();
+        let parts = split_stops(value);
+        let count = parts.len();
+
+        for (i, part) in parts.into_iter().enumerate() {
+            let part = part.trim();
+            let (color, position) = match part.rsplit_once(char::is_whitespace) {
+                Some((color, position)) if !part.ends_with(')') => (color.trim(), Some(parse_position(position)?)),
+                _ => (part, None),
+            };
+
+            let color = csscolorparser::parse(color).map_err(|e| format!("Invalid palette color '{}': {}", color, e))?;
+            stops.push(PaletteStop {
+                color,
+                position: position.unwrap_or(i as f64 / count as f64),
+            });
+        }
+
+        if stops.is_empty() {
+            return Err("The palette needs at least one color!".into());
+        }
+        if stops.windows(2).any(|v| v[1].position < v[0].position) {
+            return Err("The positions of palette colors have to be in increasing order!".into());
+        }
+
+        return Ok(Self { stops, space });
+    }
+
+    /// Gets a palette from the value of `--palette`, which is either a list of colors (see `parse`)
+    /// or the path of a `.map`, `.gpl` or `.ugr` palette file.
+    pub fn from_arg(value: &str, space: GradientSpace) -> Result<Self, String> {
+        return match palette_file::get_format(value) {
+            Some(format) => palette_file::load(value, format, space),
+            None => Self::parse(value, space),
+        };
+    }
+
+    /// Builds the table colors are looked up in.
+    /// Each color is packed into a `u32` as RGBA bytes (little endian, which is what GLSL's `unpackUnorm4x8` expects.)
+    pub fn get_table(&self) -> Vec<u32> {
+        return (0..PALETTE_SIZE)
+            .map(|i| {
+                let color = self.interpolate(i as f64 / PALETTE_SIZE as f64);
+                u32::from_le_bytes(color.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8))
+            })
+            .collect();
+    }
+
+    /// Gets the color (as RGBA values from 0 to 1) at `position` in the palette.
+    fn interpolate(&self, position: f64) -> [f64; 4] {
+
+        let count = self.stops.len();
+
+        // The stops on either side of the position, wrapping around the ends of the palette
+        let next = self.stops.iter().position(|v| v.position > position).unwrap_or(count);
+        let (start, start_position) = match next {
+            0 => (&self.stops[count - 1], self.stops[count - 1].position - 1.0),
+            _ => (&self.stops[next - 1], self.stops[next - 1].position),
+        };
+        let (end, end_position) = match next == count {
+            true => (&self.stops[0], self.stops[0].position + 1.0),
+            false => (&self.stops[next], self.stops[next].position),
+        };
+
+        let amount = match end_position > start_position {
+            true => (position - start_position) / (end_position - start_position),
+            false => 0.0,
+        };
+
+        let start_color = self.srgb_to_space(&start.color);
+        let end_color = self.srgb_to_space(&end.color);
+        let mixed = [0, 1, 2].map(|i| start_color[i] + (end_color[i] - start_color[i]) * amount);
+        let [r, g, b] = self.space_to_srgb(mixed);
+
+        return [r, g, b, start.color.a + (end.color.a - start.color.a) * amount];
+    }
+
+    /// Converts a color into the space colors are interpolated in.
+    fn srgb_to_space(&self, color: &csscolorparser::Color) -> [f64; 3] {
+        let srgb = [color.r, color.g, color.b];
+        return match self.space {
+            GradientSpace::Srgb => srgb,
+            GradientSpace::Linear => srgb.map(srgb_to_linear),
+            GradientSpace::Oklab => linear_to_oklab(srgb.map(srgb_to_linear)),
+        };
+    }
+
+    /// Converts a color from the space colors are interpolated in back to sRGB.
+    fn space_to_srgb(&self, color: [f64; 3]) -> [f64; 3] {
+        return match self.space {
+            GradientSpace::Srgb => color,
+            GradientSpace::Linear => color.map(linear_to_srgb),
+            GradientSpace::Oklab => oklab_to_linear(color).map(|v| linear_to_srgb(v.clamp(0.0, 1.0))),
+        };
+    }
+
+}
+
+/// Splits a list of palette stops on the commas which aren't inside of parentheses.
+fn split_stops(value: &str) -> Vec<&str> {
+    let mut stops = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, character) in value.char_indices() {
+        match character {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                stops.push(&value[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    stops.push(&value[start..]);
+    return stops.into_iter().filter(|v| !v.trim().is_empty()).collect();
+}
+
+/// Parses the position of a palette stop, either from 0 to 1 or as a percentage.
+fn parse_position(value: &str) -> Result<f64, String> {
+    let position = match value.strip_suffix('%') {
+        Some(percentage) => percentage.parse::<f64>().map(|v| v / 100.0),
+        None => value.parse::<f64>(),
+    }.map_err(|_| format!("Invalid palette position '{}'", value))?;
+
+    if !(0.0..=1.0).contains(&position) {
+        return Err(format!("Palette position '{}' has to be between 0 and 1 (or 0% and 100%)", value));
+    }
+    return Ok(position);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unpacks a table color into RGBA bytes.
+    fn get_color(table: &[u32], index: usize) -> [u8; 4] {
+        return table[index].to_le_bytes();
+    }
+
+    #[test]
+    fn parse_stops() {
+        let palette = Palette::parse("#000 0, red 25%, rgb(255, 200, 0) 0.5, white", GradientSpace::Srgb).unwrap();
+        let positions: Vec<f64> = palette.stops.iter().map(|v| v.position).collect();
+        // Stops without a position are spread evenly
+        assert_eq!(positions, vec![0.0, 0.25, 0.5, 0.75]);
+        assert_eq!(palette.stops[2].color.to_rgba8(), [255, 200, 0, 255]);
+    }
+
+    #[test]
+    fn invalid_palettes() {
+        assert!(Palette::parse("", GradientSpace::Oklab).is_err());
+        assert!(Palette::parse("notacolor", GradientSpace::Oklab).is_err());
+        assert!(Palette::parse("red 50%, blue 25%", GradientSpace::Oklab).is_err());
+        assert!(Palette::parse("red 150%", GradientSpace::Oklab).is_err());
+        assert!(Palette::parse("red high", GradientSpace::Oklab).is_err());
+    }
+
+    #[test]
+    fn table_interpolates_and_wraps() {
+        let palette = Palette::parse("#000 0, #fff 0.5", GradientSpace::Srgb).unwrap();
+        let table = palette.get_table();
+        assert_eq!(table.len(), PALETTE_SIZE);
+
+        assert_eq!(get_color(&table, 0), [0, 0, 0, 255]);
+        assert_eq!(get_color(&table, PALETTE_SIZE / 4), [128, 128, 128, 255]);
+        assert_eq!(get_color(&table, PALETTE_SIZE / 2), [255, 255, 255, 255]);
+        // The end of the palette blends back into the first color
+        assert_eq!(get_color(&table, PALETTE_SIZE * 3 / 4), [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn blending_spaces() {
+        let middle = |space| get_color(&Palette::parse("#000 0, #fff 0.5", space).unwrap().get_table(), PALETTE_SIZE / 4)[0];
+        // Blending in linear light gives a brighter middle than blending the sRGB values
+        assert!(middle(GradientSpace::Linear) > middle(GradientSpace::Srgb));
+        // OKLab lightness is close to how bright colors look, so its middle is a darker grey than either
+        assert!(middle(GradientSpace::Oklab) < middle(GradientSpace::Srgb));
+    }
+
+    #[test]
+    fn palettes_from_args() {
+        // Lists of colors get parsed directly
+        let palette = Palette::from_arg("red, blue", GradientSpace::Linear).unwrap();
+        assert_eq!(palette.stops.len(), 2);
+        assert_eq!(palette.space, GradientSpace::Linear);
+
+        // Palette files are loaded from their path
+        let path = std::env::temp_dir().join(format!("kyros_palette_test_{}.map", std::process::id()));
+        std::fs::write(&path, "0 0 0\n255 0 0\n0 0 255\n").unwrap();
+        let palette = Palette::from_arg(path.to_str().unwrap(), GradientSpace::Oklab);
+        std::fs::remove_file(&path).unwrap();
+        let palette = palette.unwrap();
+        assert_eq!(palette.stops.len(), 3);
+        assert_eq!(palette.stops[1].color.to_rgba8(), [255, 0, 0, 255]);
+        assert!((palette.stops[2].position - 2.0 / 3.0).abs() < 1e-12);
+
+        assert!(Palette::from_arg("missing.gpl", GradientSpace::Oklab).is_err());
+    }
+
+    #[test]
+    fn split_on_top_level_commas() {
+        assert_eq!(split_stops("rgb(1, 2, 3) 0, red,, blue"), vec!["rgb(1, 2, 3) 0", " red", " blue"]);
+    }
+}
@@ -0,0 +1,221 @@
+/*
+  File for importing palettes from other fractal programs.
+  Supports Fractint `.map` files, GIMP `.gpl` palettes & Ultra Fractal `.ugr` gradients.
+  Errors point to the line of the file which couldn't be read.
+*/
+
+use std::fs;
+use std::path::Path;
+
+use crate::colors::palette::{GradientSpace, Palette, PaletteStop};
+
+/// The amount of positions in an Ultra Fractal gradient.
+const UGR_SIZE: f64 = 400.0;
+
+/// The palette file formats which can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    /// Fractint `.map` files, one `R G B` color per line
+    Map,
+    /// GIMP `.gpl` palettes, a `GIMP Palette` header followed by one `R G B name` color per line
+    Gpl,
+    /// Ultra Fractal `.ugr` gradients, `index=N color=C` stops inside of a named gradient block
+    Ugr,
+}
+
+/// Gets the format of a palette file from its extension (if it is a palette file.)
+pub fn get_format(path: &str) -> Option<PaletteFormat> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+    return match extension.as_str() {
+        "map" => Some(PaletteFormat::Map),
+        "gpl" => Some(PaletteFormat::Gpl),
+        "ugr" => Some(PaletteFormat::Ugr),
+        _ => None,
+    };
+}
+
+/// Loads the palette file at `path` in `format`.
+pub fn load(path: &str, format: PaletteFormat, space: GradientSpace) -> Result<Palette, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Can't open palette file `{}`: {}", path, e))?;
+
+    let colors = match format {
+        PaletteFormat::Map => parse_map(&contents),
+        PaletteFormat::Gpl => parse_gpl(&contents),
+        PaletteFormat::Ugr => parse_ugr(&contents),
+    }.map_err(|(line, message)| format!("{}:{}: {}", path, line, message))?;
+
+    if colors.is_empty() {
+        return Err(format!("{}: The palette file doesn't have any colors!", path));
+    }
+
+    // Formats without positions get their colors spread evenly
+    let count = colors.len();
+    let stops = colors
+        .into_iter()
+        .enumerate()
+        .map(|(i, (color, position))| PaletteStop {
+            color,
+            position: position.unwrap_or(i as f64 / count as f64),
+        })
+        .collect();
+
+    return Ok(Palette { stops, space });
+}
+
+/// A color & its position (if the format has positions.)
+type FileColor = (csscolorparser::Color, Option<f64>);
+
+/// An error & the line (starting at 1) it happened on.
+type FileError = (usize, String);
+
+/// Parses a Fractint `.map` file.
+/// Anything after the three color values on a line is a comment.
+fn parse_map(contents: &str) -> Result<Vec<FileColor>, FileError> {
+    let mut colors = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        colors.push((parse_rgb(line, i + 1)?, None));
+    }
+    return Ok(colors);
+}
+
+/// Parses a GIMP `.gpl` palette.
+/// Lines starting with `#` are comments & the `Name` & `Columns` lines are ignored.
+fn parse_gpl(contents: &str) -> Result<Vec<FileColor>, FileError> {
+    let mut lines = contents.lines().enumerate();
+
+    match lines.next() {
+        Some((_, header)) if header.trim() == "GIMP Palette" => {},
+        _ => return Err((1, "Expected the `GIMP Palette` header".into())),
+    }
+
+    let mut colors = Vec::new();
+    for (i, line) in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        colors.push((parse_rgb(line, i + 1)?, None));
+    }
+    return Ok(colors);
+}
+
+/// Parses the first gradient of an Ultra Fractal `.ugr` file.
+/// Colors are stored as a number with red in the lowest byte (`0xBBGGRR`) & positions go from 0 to 399.
+/// The opacity section is ignored.
+fn parse_ugr(contents: &str) -> Result<Vec<FileColor>, FileError> {
+    let mut colors = Vec::new();
+    let mut in_gradient = false;
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+
+        if !in_gradient {
+            in_gradient = line.ends_with('{');
+            continue;
+        }
+        if line.starts_with('}') {
+            break;
+        }
+        if line == "opacity:" {
+            // The colors are always before the opacity section
+            break;
+        }
+
+        let mut index = None;
+        let mut color = None;
+        for token in line.split_whitespace() {
+            if let Some(value) = token.strip_prefix("index=") {
+                index = Some(value.parse::<f64>().map_err(|_| (i + 1, format!("Invalid gradient index '{}'", value)))?);
+            }
+            if let Some(value) = token.strip_prefix("color=") {
+                color = Some(value.parse::<u32>().map_err(|_| (i + 1, format!("Invalid gradient color '{}'", value)))?);
+            }
+        }
+
+        match (index, color) {
+            (Some(index), Some(color)) => {
+                if !(0.0..UGR_SIZE).contains(&index) {
+                    return Err((i + 1, format!("Gradient index {} has to be from 0 to {}", index, UGR_SIZE - 1.0)));
+                }
+                if colors.last().is_some_and(|v: &FileColor| v.1 > Some(index / UGR_SIZE)) {
+                    return Err((i + 1, "Gradient indexes have to be in increasing order".into()));
+                }
+                let [r, g, b, _] = color.to_le_bytes();
+                colors.push((csscolorparser::Color::from_rgba8(r, g, b, 255), Some(index / UGR_SIZE)));
+            },
+            (Some(_), None) => return Err((i + 1, "Expected a color after the gradient index".into())),
+            (None, Some(_)) => return Err((i + 1, "Expected an index before the gradient color".into())),
+            (None, None) => {},
+        }
+    }
+
+    if !in_gradient {
+        return Err((1, "Couldn't find a gradient (expected a line ending with `{`)".into()));
+    }
+    return Ok(colors);
+}
+
+/// Parses the first three values on a line as red, green & blue (from 0 to 255.)
+fn parse_rgb(line: &str, line_number: usize) -> Result<csscolorparser::Color, FileError> {
+    let mut values = line.split_whitespace();
+    let mut channels = [0u8; 3];
+    for channel in channels.iter_mut() {
+        let value = values.next().ok_or((line_number, format!("Expected 3 color values but found '{}'", line.trim())))?;
+        *channel = value.parse::<u8>().map_err(|_| (line_number, format!("Invalid color value '{}' (has to be from 0 to 255)", value)))?;
+    }
+    return Ok(csscolorparser::Color::from_rgba8(channels[0], channels[1], channels[2], 255));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_from_extensions() {
+        assert_eq!(get_format("palettes/fire.MAP"), Some(PaletteFormat::Map));
+        assert_eq!(get_format("ocean.gpl"), Some(PaletteFormat::Gpl));
+        assert_eq!(get_format("gradients.ugr"), Some(PaletteFormat::Ugr));
+        assert_eq!(get_format("red, blue"), None);
+        assert_eq!(get_format("notes.txt"), None);
+    }
+
+    #[test]
+    fn map_files() {
+        let colors = parse_map("0 0 0\n\n255 128 0 orange comment\n").unwrap();
+        assert_eq!(colors.len(), 2);
+        assert_eq!(colors[1].0.to_rgba8(), [255, 128, 0, 255]);
+        assert!(colors[1].1.is_none());
+
+        assert_eq!(parse_map("0 0 0\n1 2\n").unwrap_err().0, 2);
+        assert_eq!(parse_map("0 0 256\n").unwrap_err().0, 1);
+    }
+
+    #[test]
+    fn gpl_files() {
+        let colors = parse_gpl("GIMP Palette\nName: Test\nColumns: 4\n# comment\n 10  20  30\tUntitled\n").unwrap();
+        assert_eq!(colors.len(), 1);
+        assert_eq!(colors[0].0.to_rgba8(), [10, 20, 30, 255]);
+
+        assert_eq!(parse_gpl("0 0 0\n").unwrap_err().0, 1);
+        assert_eq!(parse_gpl("GIMP Palette\n1 2 x\n").unwrap_err().0, 2);
+    }
+
+    #[test]
+    fn ugr_files() {
+        let contents = "test {\ngradient:\n  title=\"Test\" smooth=no\n  index=0 color=255\n  index=200 color=16711680\nopacity:\n  index=0 opacity=255\n}\n";
+        let colors = parse_ugr(contents).unwrap();
+        assert_eq!(colors.len(), 2);
+        // Red is stored in the lowest byte
+        assert_eq!(colors[0].0.to_rgba8(), [255, 0, 0, 255]);
+        assert_eq!(colors[1].0.to_rgba8(), [0, 0, 255, 255]);
+        assert_eq!(colors[1].1, Some(0.5));
+
+        assert_eq!(parse_ugr("no gradient here").unwrap_err().0, 1);
+        assert_eq!(parse_ugr("test {\n  index=10 color=0\n  index=5 color=0\n}").unwrap_err().0, 3);
+        assert_eq!(parse_ugr("test {\n  index=400 color=0\n}").unwrap_err().0, 2);
+        assert_eq!(parse_ugr("test {\n  index=1\n}").unwrap_err().0, 2);
+    }
+}
@@ -2,9 +2,10 @@
 
 use hsv;
 
+use crate::colors::palette::PALETTE_SIZE;
 use crate::structs::Config;
 
-pub trait ColorProfile {
+pub trait ColorProfile: Sync {
     fn get_foreground(&self) -> &Vec<u8>;
     fn get_background(&self) -> &Vec<u8>;
     fn method(&self, value: f64, max_i: f64) -> Vec<u8>;
@@ -34,25 +35,65 @@ impl ColorProfile for RgbProfile {
     }
 }
 
+/// Colors pixels from the table of a gradient palette instead of the hue.
+/// The GPU looks colors up in the same table so the output matches.
+pub struct PaletteProfile {
+    pub foreground: Vec<u8>,
+    pub background: Vec<u8>,
+    pub table: Vec<u32>,
+}
+
+impl ColorProfile for PaletteProfile {
+
+    fn get_foreground(&self) -> &Vec<u8> { &self.foreground }
+    fn get_background(&self) -> &Vec<u8> { &self.background }
+
+    /// `hue` (from 0 to 360) picks the position in the palette & `value` darkens the color.
+    fn method(&self, hue: f64, value: f64) -> Vec<u8> {
+        let index = (hue / 360.0 * PALETTE_SIZE as f64) as usize % PALETTE_SIZE;
+        let [r, g, b, a] = self.table[index].to_le_bytes();
+        let value = value.clamp(0.0, 1.0);
+        Vec::from([
+            (r as f64 * value).round() as u8,
+            (g as f64 * value).round() as u8,
+            (b as f64 * value).round() as u8,
+            a,
+        ])
+    }
+}
+
 pub fn get_profile(config: &Config) -> Box<dyn ColorProfile> {
 
     let background = config.background.to_linear_rgba_u8();
     let foreground = config.foreground.to_linear_rgba_u8();
 
+    let background = Vec::from([
+        background.0,
+        background.1,
+        background.2,
+        background.3,
+    ]);
+    let foreground = Vec::from([
+        foreground.0,
+        foreground.1,
+        foreground.2,
+        foreground.3,
+    ]);
+
+    if let Some(palette) = &config.palette {
+        return Box::new(
+            PaletteProfile{
+                background,
+                foreground,
+                table: palette.get_table(),
+            }
+        );
+    }
+
     return Box::new(
         RgbProfile{
-            background: Vec::from([
-                background.0,
-                background.1,
-                background.2,
-                background.3,
-            ]),
-            foreground: Vec::from([
-                foreground.0,
-                foreground.1,
-                foreground.2,
-                foreground.3,
-            ]),
+            background,
+            foreground,
         }
     );
 }
@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
 use crate::cli::Args;
+use crate::structs::{Complex, Config};
 
 use clap::error::ErrorKind;
 use clap::CommandFactory;
@@ -10,11 +11,18 @@ use clap::CommandFactory;
       Date : 6/21/2023
 */
 
-pub trait Shadows {
+pub trait Shadows: Sync {
     fn get_alias(&self) -> String;
     fn get_description(&self) -> String;
     fn method(&self, n: f64) -> f64;
     fn gpu_method(&self) -> String;
+    /// Gets the lightness of a pixel which has a normal (the direction away from the set.)
+    /// By default the normal is ignored.
+    fn normal_method(&self, n: f64, _normal: Complex<f64>, _config: &Config) -> f64 {
+        self.method(n)
+    }
+    /// If the derivative of z needs to be tracked to get normals.
+    fn needs_derivative(&self) -> bool { false }
 }
 
 /// Rotational Coloring function for generation. Uses HSV rotational color.
@@ -62,18 +70,48 @@ impl Shadows for MODULUS {
     }
 }
 
+pub struct NORMAL {}
+impl Shadows for NORMAL {
+    fn get_alias(&self) -> String { "NORMAL".into() }
+    fn get_description(&self) -> String { "3D lighting using the normal of the distance estimate".into() }
+    /// Only used without a config, CPU coloring always calls `normal_method` (with a zero normal for
+    /// pixels which don't have one.)
+    fn method(&self, _n: f64) -> f64 {
+        return 1.0;
+    }
+    fn gpu_method(&self) -> String {
+        "
+        vec2 light = vec2(cos(radians(light_angle)), sin(radians(light_angle)));
+        return max((dot(normal, light) + light_height) / (1.0 + light_height), 0.0);
+        ".into()
+    }
+    fn normal_method(&self, _n: f64, normal: Complex<f64>, config: &Config) -> f64 {
+        let angle = config.light_angle.to_radians();
+        let lightness = normal.real * angle.cos() + normal.imaginary * angle.sin();
+        return ((lightness + config.light_height) / (1.0 + config.light_height)).max(0.0);
+    }
+    fn needs_derivative(&self) -> bool { true }
+}
+
 /// Function for getting the shadow formula from config
+/// Exits with the list of shadows if it can't be found.
 pub fn get_shadow(shadow: &str) -> &dyn Shadows{
-    let shadows: Vec<&dyn Shadows> = vec![
+    return try_get_shadow(shadow).unwrap_or_else(|e| Args::command().error(ErrorKind::InvalidValue, e).exit());
+}
+
+/// Same as `get_shadow` but returns the error message instead of exiting.
+pub fn try_get_shadow(shadow: &str) -> Result<&'static dyn Shadows, String> {
+    let shadows: Vec<&'static dyn Shadows> = vec![
         &NONE {},
         &MINIMAL {},
         &MODULUS {},
+        &NORMAL {},
     ];
 
     // Tries to find function in shadows array
     for method in shadows.clone() {
         if method.get_alias() == shadow.to_string() {
-            return method;
+            return Ok(method);
         }
     }
 
@@ -83,9 +121,32 @@ pub fn get_shadow(shadow: &str) -> &dyn Shadows{
         .collect::<Vec<String>>()
         .join("\n");
 
-    // If not found throw error
-    Args::command().error(
-        ErrorKind::InvalidValue,
-        format!("Shadow method '{}' not found!\n\nAllowed Shadows:\n{}", shadow, shadow_string)
-    ).exit();
+    // If not found return error
+    return Err(format!("Shadow method '{}' not found!\n\nAllowed Shadows:\n{}", shadow, shadow_string));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_lighting() {
+        let config = Config { light_angle: 90.0, light_height: 1.0, ..Config::default() };
+        let shadow = get_shadow("NORMAL");
+        assert!(shadow.needs_derivative());
+
+        let lightness = |real, imaginary| shadow.normal_method(0.0, Complex { real, imaginary }, &config);
+        // Facing the light, facing away from it & the zero normal used for pixels without one
+        assert!((lightness(0.0, 1.0) - 1.0).abs() < 1e-12);
+        assert_eq!(lightness(0.0, -1.0), 0.0);
+        assert!((lightness(0.0, 0.0) - 0.5).abs() < 1e-12);
+        assert!((lightness(1.0, 0.0) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn other_shadows_ignore_normals() {
+        let shadow = get_shadow("NONE");
+        assert!(!shadow.needs_derivative());
+        assert_eq!(shadow.normal_method(3.0, Complex::from_real(1.0), &Config::default()), shadow.method(3.0));
+    }
 }
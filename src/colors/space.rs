@@ -0,0 +1,114 @@
+/*
+  File for converting between color spaces.
+  Colors in images are sRGB encoded, however blending them should be done in linear light.
+*/
+
+/// Converts an sRGB encoded channel (from 0 to 1) to linear light.
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        return value / 12.92;
+    }
+    return ((value + 0.055) / 1.055).powf(2.4);
+}
+
+/// Converts a linear light channel (from 0 to 1) to sRGB encoding.
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        return value * 12.92;
+    }
+    return 1.055 * value.powf(1.0 / 2.4) - 0.055;
+}
+
+/// Averages RGBA colors in linear light.
+/// Colors are weighted by their alpha so transparent colors don't darken the result.
+pub fn average_colors(colors: &[Vec<u8>]) -> Vec<u8> {
+
+    let mut total = [0.0; 4];
+    for color in colors {
+        let alpha = color[3] as f64 / 255.0;
+        for channel in 0..3 {
+            total[channel] += srgb_to_linear(color[channel] as f64 / 255.0) * alpha;
+        }
+        total[3] += alpha;
+    }
+
+    let mut average = vec![0u8; 4];
+    if total[3] > 0.0 {
+        for channel in 0..3 {
+            average[channel] = (linear_to_srgb(total[channel] / total[3]) * 255.0).round() as u8;
+        }
+    }
+    average[3] = (total[3] / colors.len() as f64 * 255.0).round() as u8;
+
+    return average;
+}
+
+/// Converts a linear light RGB color to OKLab (a perceptually uniform color space.)
+pub fn linear_to_oklab(color: [f64; 3]) -> [f64; 3] {
+    let [r, g, b] = color;
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    return [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ];
+}
+
+/// Converts an OKLab color to linear light RGB.
+pub fn oklab_to_linear(color: [f64; 3]) -> [f64; 3] {
+    let [lightness, a, b] = color;
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    return [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trip() {
+        for value in 0..=255 {
+            let value = value as f64 / 255.0;
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-12);
+        }
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-12);
+        // Middle grey in sRGB is about a fifth of the light
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+
+    #[test]
+    fn averages_in_linear_light() {
+        // Black & white average to a grey brighter than the sRGB midpoint
+        let average = average_colors(&[vec![0, 0, 0, 255], vec![255, 255, 255, 255]]);
+        assert_eq!(average, vec![188, 188, 188, 255]);
+
+        // Transparent colors don't change the hue but do lower the alpha
+        let average = average_colors(&[vec![255, 0, 0, 255], vec![0, 0, 255, 0]]);
+        assert_eq!(average, vec![255, 0, 0, 128]);
+
+        assert_eq!(average_colors(&[vec![9, 9, 9, 0]]), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        // White has full lightness & no color
+        let white = linear_to_oklab([1.0, 1.0, 1.0]);
+        assert!((white[0] - 1.0).abs() < 1e-6 && white[1].abs() < 1e-6 && white[2].abs() < 1e-6);
+
+        for color in [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.2, 0.5, 0.9], [0.05, 0.8, 0.3]] {
+            let round_trip = oklab_to_linear(linear_to_oklab(color));
+            for channel in 0..3 {
+                assert!((round_trip[channel] - color[channel]).abs() < 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+/*
+  File for reading & writing render settings as config files.
+  Config files hold the same fields as the config saved in images, either as TOML or as JSON
+  (picked by the extension of the file.) Only the fields which are in the file get set, the rest
+  come from the CLI arguments. Unknown fields are rejected so typos don't get silently ignored.
+*/
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::structs::{Config, MathFrame};
+
+/// The formats config files can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+/// Gets the format of a config file from its extension.
+pub fn get_format(filename: &str) -> Result<ConfigFormat, String> {
+    let extension = Path::new(filename).extension().and_then(|v| v.to_str()).unwrap_or_default();
+    return match extension.to_ascii_lowercase().as_str() {
+        "toml" => Ok(ConfigFormat::Toml),
+        "json" => Ok(ConfigFormat::Json),
+        _ => Err(format!("Can't tell the format of `{}` (expected a .toml or .json file)!", filename)),
+    };
+}
+
+/// Reads the config file `filename`, fields which aren't in the file are taken from `defaults`.
+pub fn read_config_file(filename: &str, defaults: &Config) -> Result<Config, Box<dyn Error>> {
+    let file = read_file(filename)?;
+    let config = parse_config(file, defaults).map_err(|e| format!("Invalid config file `{}`: {}", filename, e))?;
+    return Ok(config);
+}
+
+/// Reads a TOML or JSON file into a JSON value.
+pub fn read_file(filename: &str) -> Result<Value, Box<dyn Error>> {
+    let format = get_format(filename)?;
+    let text = fs::read_to_string(filename).map_err(|e| format!("Can't open `{}`: {}", filename, e))?;
+    let value = match format {
+        ConfigFormat::Toml => toml::from_str(&text).map_err(|e| format!("Can't parse `{}`: {}", filename, e))?,
+        ConfigFormat::Json => serde_json::from_str(&text).map_err(|e| format!("Can't parse `{}`: {}", filename, e))?,
+    };
+    return Ok(value);
+}
+
+/// Creates a config from the fields in `file`, the rest are taken from `defaults`.
+pub fn parse_config(file: Value, defaults: &Config) -> Result<Config, Box<dyn Error>> {
+    let sets_frame = file.get("math_frame").is_some();
+    let sets_chunk_size = file.get("chunk_size").is_some();
+
+    let mut value = serde_json::to_value(defaults)?;
+    merge(&mut value, file);
+    let mut config: Config = serde_json::from_value(value)?;
+
+    // Without a frame in the file, the default area is fit to the size of the file's image
+    let resized = (config.size_x, config.size_y) != (defaults.size_x, defaults.size_y);
+    if !sets_frame && resized {
+        let frame = &defaults.math_frame;
+        config.math_frame = MathFrame::fit(
+            frame.factor_x * (defaults.size_x.max(2) - 1) as f64,
+            frame.factor_y * (defaults.size_y.max(2) - 1) as f64,
+            frame.offset_x,
+            frame.offset_y,
+            config.size_x,
+            config.size_y,
+            false,
+        );
+    }
+    if !sets_chunk_size && resized {
+        config.chunk_size = config.size_x.max(config.size_y) as u64;
+    }
+
+    return Ok(config);
+}
+
+/// Writes `config` to `filename` (in the format given by its extension.)
+pub fn write_config_file(filename: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    return write_file(filename, config);
+}
+
+/// Writes `value` to `filename` as TOML or JSON (picked by its extension.)
+pub fn write_file<T: Serialize>(filename: &str, value: &T) -> Result<(), Box<dyn Error>> {
+    let text = match get_format(filename)? {
+        ConfigFormat::Toml => toml::to_string_pretty(value)?,
+        ConfigFormat::Json => serde_json::to_string_pretty(value)?,
+    };
+    fs::write(filename, text).map_err(|e| format!("Can't write `{}`: {}", filename, e))?;
+    return Ok(());
+}
+
+/// Copies the values of `file` over `base`.
+/// Tables are merged so only the fields which are set in the file change.
+pub fn merge(base: &mut Value, file: Value) {
+    match (base, file) {
+        (Value::Object(base), Value::Object(file)) => {
+            for (key, value) in file {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => { base.insert(key, value); },
+                }
+            }
+        },
+        (base, file) => *base = file,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `text` to a temporary file with `extension` & reads it as a config file.
+    fn read_text(text: &str, extension: &str, defaults: &Config) -> Result<Config, Box<dyn Error>> {
+        let filename = std::env::temp_dir()
+            .join(format!("kyros_config_test_{}_{}.{}", std::process::id(), text.len(), extension))
+            .to_string_lossy()
+            .into_owned();
+        fs::write(&filename, text).unwrap();
+        let config = read_config_file(&filename, defaults);
+        fs::remove_file(&filename).unwrap();
+        return config;
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(get_format("render.TOML"), Ok(ConfigFormat::Toml));
+        assert_eq!(get_format("render.json"), Ok(ConfigFormat::Json));
+        assert!(get_format("render.yaml").is_err());
+    }
+
+    #[test]
+    fn merge_only_sets_fields_in_the_file() {
+        let mut base = serde_json::json!({ "max_i": 256, "math_frame": { "factor_x": 1.0, "offset_x": -2.0 }, "palette": null });
+        merge(&mut base, serde_json::json!({ "math_frame": { "offset_x": 0.5 }, "palette": { "space": "Oklab" } }));
+        assert_eq!(base, serde_json::json!({
+            "max_i": 256,
+            "math_frame": { "factor_x": 1.0, "offset_x": 0.5 },
+            "palette": { "space": "Oklab" },
+        }));
+    }
+
+    #[test]
+    fn files_override_defaults() {
+        let defaults = Config::default();
+        let config = read_text("max_i = 1000\nsmooth = true\n", "toml", &defaults).unwrap();
+        assert_eq!(config.max_i, 1000);
+        assert!(config.smooth);
+        assert_eq!(config.gen_formula, defaults.gen_formula);
+
+        let config = read_text(r#"{ "size_x": 1024, "size_y": 1024 }"#, "json", &defaults).unwrap();
+        // The default area is kept at the new size
+        assert_eq!(config.math_frame.get_point(0.0, 0.0).real, defaults.math_frame.get_point(0.0, 0.0).real);
+        assert_eq!(config.chunk_size, 1024);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let defaults = Config::default();
+        assert!(read_text("max_iterations = 1000\n", "toml", &defaults).is_err());
+        assert!(read_text(r#"{ "math_frame": { "factor_z": 1.0 } }"#, "json", &defaults).is_err());
+        assert!(read_text("max_i = \"many\"\n", "toml", &defaults).is_err());
+    }
+}
@@ -0,0 +1,798 @@
+/*
+  File for the multithreaded CPU renderer
+*/
+
+use indicatif::{ProgressBar, ProgressStyle};
+use num_traits::Float;
+use parking_lot::Mutex;
+
+use crate::colors::histogram::{Histogram, PREVIEW_SIZE};
+use crate::colors::painter::Painter;
+use crate::cli::default_thread_count;
+use crate::colors::shadows::get_shadow;
+use crate::colors::space::average_colors;
+use crate::math::escape::{get_escape, Escape};
+use crate::math::formula::{get_formula, Formula};
+use crate::math::interior::{get_periodicity_tolerance, in_main_bulbs, use_interior_checks, Periodicity};
+use crate::math::orbit_trap::Trap;
+use crate::math::perturbation::ReferenceOrbit;
+use crate::raw;
+use crate::structs::{Complex, Config, Precision, Sample, Strategy};
+use crate::Level;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+/// The amount of lines a thread renders before asking for more work.
+/// Bands are kept small so slow regions (like the inside of the set) get spread across threads.
+const LINES_PER_BAND: u32 = 8;
+
+/// The size of the tiles bands get split into when rendering by subdivision.
+/// Bands are one tile high.
+const TILE_SIZE: u32 = 32;
+
+/// Holds everything needed to compute pixels on the CPU.
+pub struct CpuRenderer<'a> {
+    config: &'a Config,
+    painter: Painter<'a>,
+    generator_function: Box<dyn Formula>,
+    escape_function: &'a dyn Escape,
+    /// The value z starts at when not using julia settings (if the formula sets one)
+    start: Option<Complex<f64>>,
+    /// The roots pixels get colored by if they converge to them
+    roots: Vec<Complex<f64>>,
+    /// If the derivative of z is needed (for distance estimation or normals)
+    track_derivative: bool,
+    /// If pixels inside the set can be found without iterating up to `max_i`
+    interior_checks: bool,
+    reference_orbit: Option<ReferenceOrbit>,
+    /// The trap pixels get colored by (for orbit trap coloring)
+    orbit_trap: Option<Trap>,
+    /// The amount of pixels re-rendered by adaptive supersampling
+    refined_pixels: AtomicU64,
+    /// The amount of samples generated for the pixels re-rendered by adaptive supersampling
+    refined_samples: AtomicU64,
+}
+
+impl<'a> CpuRenderer<'a> {
+
+    /// Function for creating a new renderer from config.
+    /// Returns an error message if the deep zoom settings, the orbit trap or the formula's roots can't be used.
+    pub fn new(config: &'a Config) -> Result<Self, String> {
+        let generator_function = get_formula(config);
+        let escape_function = get_escape(config);
+        let reference_orbit = match config.deep_zoom {
+            Some(_) => Some(ReferenceOrbit::new(config)?),
+            None => None,
+        };
+        let orbit_trap = match &config.orbit_trap {
+            Some(settings) => Some(Trap::new(settings)?),
+            None => None,
+        };
+        let roots = generator_function.get_roots()?;
+        let mut renderer = Self {
+            config,
+            painter: Painter::new(config, roots.len()),
+            start: generator_function.get_start(),
+            roots,
+            interior_checks: use_interior_checks(config, generator_function.as_ref(), escape_function),
+            generator_function,
+            escape_function,
+            track_derivative: config.distance_estimation || get_shadow(config.shadow_formula.as_str()).needs_derivative(),
+            reference_orbit,
+            orbit_trap,
+            refined_pixels: AtomicU64::new(0),
+            refined_samples: AtomicU64::new(0),
+        };
+
+        // Histogram coloring needs the distribution of the whole image before any pixel can be colored
+        if config.histogram {
+            let histogram = renderer.get_preview_histogram();
+            renderer.painter.set_histogram(histogram);
+        }
+        return Ok(renderer);
+    }
+
+    /// Gets the distribution pixels are colored with (if histogram coloring is used.)
+    pub fn get_histogram(&self) -> Option<&Histogram> {
+        return self.painter.get_histogram();
+    }
+
+    /// Logs information about the finished generation.
+    pub fn log_summary(&self) {
+        if let Some(reference_orbit) = &self.reference_orbit {
+            log::info!("Rebased {} pixels onto the reference orbit to avoid glitches", reference_orbit.get_rebased_pixels());
+        }
+        if self.uses_adaptive_samples() {
+            log::info!(
+                "Refined {} pixels with {} samples (adaptive supersampling, {} new samples)",
+                self.refined_pixels.load(Ordering::Relaxed),
+                self.config.adaptive_samples,
+                self.refined_samples.load(Ordering::Relaxed),
+            );
+        }
+    }
+
+    /// If pixels get refined with adaptive supersampling.
+    /// Refining with fewer samples than every pixel already has would make pixels worse so it's skipped.
+    fn uses_adaptive_samples(&self) -> bool {
+        return self.config.adaptive_samples > self.config.samples;
+    }
+
+    /// The amount of bytes each pixel takes up in the output buffer.
+    pub fn bytes_per_pixel(&self) -> usize {
+        return 3 + self.config.rgba as usize;
+    }
+
+    /// The amount of bytes each line takes up in the output buffer.
+    pub fn bytes_per_line(&self) -> usize {
+        return self.config.size_x as usize * self.bytes_per_pixel();
+    }
+
+    /// Renders the lines starting at `first_line` into `buf`.
+    /// The amount of lines rendered is decided by the length of `buf`.
+    pub fn render_lines(&self, first_line: u32, buf: &mut [u8], progress: &ProgressBar) {
+
+        let line_length = self.bytes_per_line();
+        let end_line = first_line + (buf.len() / line_length) as u32;
+
+        // Adaptive supersampling compares pixels with their neighbours so the value of each pixel is kept.
+        // This includes the lines just outside of `buf` so the pixels on its edges have all their neighbours.
+        let values = match self.uses_adaptive_samples() {
+            false => None,
+            true => Some(PixelValues::new(first_line.saturating_sub(1), (end_line + 1).min(self.config.size_y), self.config.size_x)),
+        };
+        if let Some(values) = &values {
+            for y in (values.first_line..first_line).chain(end_line..values.end_line) {
+                for x in 0..self.config.size_x {
+                    values.store(x, y, self.get_contrast_value(&self.sample(x as f64, y as f64)));
+                }
+            }
+        }
+
+        match self.config.strategy {
+            Strategy::Pixels => self.for_each_band(first_line, buf, line_length, LINES_PER_BAND, |band_start, band| {
+                for (i, line) in band.chunks_mut(line_length).enumerate() {
+                    self.render_line(band_start + i as u32, line, values.as_ref());
+                    progress.inc(1);
+                }
+            }),
+            Strategy::Subdivide => self.for_each_band(first_line, buf, line_length, TILE_SIZE, |band_start, band| {
+                self.render_tiles(band_start, band, values.as_ref());
+                progress.inc((band.len() / line_length) as u64);
+            }),
+        }
+
+        if let Some(values) = &values {
+            self.refine_lines(first_line, buf, values);
+        }
+    }
+
+    /// Generates the lines starting at `first_line` into `buf` in the raw format (see `raw.rs`.)
+    /// Raw output stores one sample per pixel so supersampling & subdivision don't apply.
+    pub fn render_raw_lines(&self, first_line: u32, buf: &mut [u8], progress: &ProgressBar) {
+        let line_length = self.config.size_x as usize * raw::BYTES_PER_PIXEL;
+        self.for_each_band(first_line, buf, line_length, LINES_PER_BAND, |band_start, band| {
+            for (i, line) in band.chunks_mut(line_length).enumerate() {
+                let y = band_start + i as u32;
+                for (x, pixel) in line.chunks_mut(raw::BYTES_PER_PIXEL).enumerate() {
+                    raw::encode_sample(&self.sample(x as f64, y as f64), pixel);
+                }
+                progress.inc(1);
+            }
+        });
+    }
+
+    /// Collects the distribution of color values from a preview of the image (for histogram coloring.)
+    /// The preview is at most `PREVIEW_SIZE` pixels wide & high, smaller images use every pixel.
+    fn get_preview_histogram(&self) -> Histogram {
+
+        let step = self.config.size_x.max(self.config.size_y).div_ceil(PREVIEW_SIZE).max(1);
+        let width = self.config.size_x.div_ceil(step);
+        let height = self.config.size_y.div_ceil(step);
+
+        // Each value takes up 8 bytes, pixels which aren't colored by value (the background & inside of the set) are NaN
+        let line_length = width as usize * 8;
+        let mut values = vec![0u8; line_length * height as usize];
+        self.for_each_band(0, values.as_mut_slice(), line_length, LINES_PER_BAND, |band_start, band| {
+            for (i, line) in band.chunks_mut(line_length).enumerate() {
+                let y = (band_start + i as u32) * step;
+                for (x, value) in line.chunks_mut(8).enumerate() {
+                    let sample = self.sample((x as u32 * step) as f64, y as f64);
+                    let value_f64 = match sample.is_inside || sample.z_output == 0.0 {
+                        true => f64::NAN,
+                        false => sample.get_color_value(self.config),
+                    };
+                    value.copy_from_slice(&value_f64.to_le_bytes());
+                }
+            }
+        });
+
+        return Histogram::new(
+            values
+                .chunks(8)
+                .map(|v| f64::from_le_bytes(v.try_into().unwrap()))
+                .collect()
+        );
+    }
+
+    /// Re-renders the pixels whose value differs from a neighbour's by more than `config.adaptive_threshold`
+    /// with `config.adaptive_samples` samples (adaptive supersampling, never fewer than `config.samples`.)
+    fn refine_lines(&self, first_line: u32, buf: &mut [u8], values: &PixelValues) {
+
+        let line_length = self.bytes_per_line();
+        let pixel_length = self.bytes_per_pixel();
+
+        self.for_each_band(first_line, buf, line_length, LINES_PER_BAND, |band_start, band| {
+            for (i, line) in band.chunks_mut(line_length).enumerate() {
+                let y = band_start + i as u32;
+
+                for (x, pixel) in line.chunks_mut(pixel_length).enumerate() {
+                    let x = x as u32;
+                    let value = values.get(x, y);
+
+                    // Compares against the 8 surrounding pixels (the ones inside the image)
+                    let needs_refinement = (y.saturating_sub(1)..(y + 2).min(values.end_line)).any(|neighbour_y| {
+                        (x.saturating_sub(1)..(x + 2).min(values.width)).any(|neighbour_x| {
+                            (values.get(neighbour_x, neighbour_y) - value).abs() > self.config.adaptive_threshold
+                        })
+                    });
+
+                    if needs_refinement {
+                        // The first sample is the one the pixel was rendered with (unless the pixel was filled in by subdivision)
+                        let first = values.take_sample(x, y);
+                        let samples = self.config.adaptive_samples.max(self.config.samples);
+                        let color = self.supersample(x, y, samples, first.as_ref());
+                        pixel.copy_from_slice(&color[0..pixel_length]);
+                        self.refined_pixels.fetch_add(1, Ordering::Relaxed);
+                        self.refined_samples.fetch_add((samples - first.is_some() as u32) as u64, Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+
+    }
+
+    /// Runs `method` on each band of `lines_per_band` lines of `buf` (starting at `first_line`.)
+    /// Each line of `buf` takes up `line_length` bytes. The bands get handed out to `get_thread_count` threads.
+    fn for_each_band(&self, first_line: u32, buf: &mut [u8], line_length: usize, lines_per_band: u32, method: impl Fn(u32, &mut [u8]) + Sync) {
+
+        let bands = Mutex::new(
+            buf
+                .chunks_mut(line_length * lines_per_band as usize)
+                .enumerate()
+        );
+
+        thread::scope(|scope| {
+            for _ in 0..get_thread_count(self.config) {
+                scope.spawn(|| loop {
+                    let Some((band, data)) = bands.lock().next() else { break };
+                    method(first_line + band as u32 * lines_per_band, data);
+                });
+            }
+        });
+
+    }
+
+    /// Gets the value pixels are compared by (for adaptive supersampling & subdivision.)
+    /// Pixels inside the set count as having used every iteration.
+    fn get_contrast_value(&self, sample: &Sample) -> f64 {
+        return match sample.is_inside {
+            true => self.config.max_i as f64,
+            false => sample.get_value(self.config),
+        };
+    }
+
+    /// Renders a single line of the image into `line`.
+    /// The value of each pixel gets stored in `values` if it is set.
+    fn render_line(&self, y: u32, line: &mut [u8], values: Option<&PixelValues>) {
+        let pixel_length = self.bytes_per_pixel();
+        for (x, pixel) in line.chunks_mut(pixel_length).enumerate() {
+            let sample = self.sample(x as f64, y as f64);
+            pixel.copy_from_slice(&self.pixel(x as u32, y, &sample)[0..pixel_length]);
+            if let Some(values) = values {
+                values.store(x as u32, y, self.get_contrast_value(&sample));
+                values.store_sample(x as u32, y, sample);
+            }
+        }
+    }
+
+    /// Renders a band of lines by subdividing it into tiles (Mariani-Silver.)
+    /// Only the borders of tiles are generated, tiles whose border is all the same get filled in &
+    /// the others get split into 4 smaller tiles.
+    /// The value of each pixel gets stored in `values` if it is set.
+    fn render_tiles(&self, first_line: u32, data: &mut [u8], values: Option<&PixelValues>) {
+
+        let width = self.config.size_x;
+        let height = (data.len() / self.bytes_per_line()) as u32;
+        let mut band = TileBand {
+            data,
+            first_line,
+            width,
+            pixel_length: self.bytes_per_pixel(),
+            values: vec![None; (width * height) as usize],
+            pixel_values: values,
+        };
+
+        // Neighbouring tiles share the pixels on their edges
+        for x in (0..width).step_by(TILE_SIZE as usize) {
+            self.subdivide(&mut band, x, 0, (x + TILE_SIZE).min(width - 1), height - 1);
+        }
+
+        if let Some(values) = values {
+            for (i, value) in band.values.iter().enumerate() {
+                let i = i as u32;
+                values.store(i % width, first_line + i / width, value.unwrap_or_default());
+            }
+        }
+    }
+
+    /// Renders the tile from (`x0`, `y0`) to (`x1`, `y1`) (inclusive) of `band`.
+    fn subdivide(&self, band: &mut TileBand, x0: u32, y0: u32, x1: u32, y1: u32) {
+
+        // Every pixel on the border gets generated since small tiles don't get split any further
+        let first_value = self.tile_pixel(band, x0, y0);
+        let mut uniform = true;
+        for (x, y) in get_tile_border(x0, y0, x1, y1) {
+            let value = self.tile_pixel(band, x, y);
+            uniform &= value == first_value && band.pixel(x, y) == band.pixel(x0, y0);
+        }
+
+        // Tiles this small don't have any pixels inside of their border
+        if x1 - x0 < 2 || y1 - y0 < 2 {
+            return;
+        }
+
+        let (mid_x, mid_y) = ((x0 + x1) / 2, (y0 + y1) / 2);
+
+        // The guard also checks the lines through the middle of the tile, which catches features that don't touch the border.
+        // These pixels are on the borders of the smaller tiles so they don't get generated twice.
+        if uniform && self.config.subdivision_guard {
+            let mut middle = (x0..=x1).map(|x| (x, mid_y)).chain((y0..=y1).map(|y| (mid_x, y)));
+            uniform = middle.all(|(x, y)| {
+                self.tile_pixel(band, x, y) == first_value && band.pixel(x, y) == band.pixel(x0, y0)
+            });
+        }
+
+        if uniform {
+            let color = band.pixel(x0, y0).to_vec();
+            for y in y0 + 1..y1 {
+                for x in x0 + 1..x1 {
+                    if band.values[band.index(x, y)].is_none() {
+                        band.set_pixel(x, y, &color, first_value);
+                    }
+                }
+            }
+            return;
+        }
+
+        self.subdivide(band, x0, y0, mid_x, mid_y);
+        self.subdivide(band, mid_x, y0, x1, mid_y);
+        self.subdivide(band, x0, mid_y, mid_x, y1);
+        self.subdivide(band, mid_x, mid_y, x1, y1);
+    }
+
+    /// Generates the pixel at (`x`, `y`) of `band` (if it hasn't been already) & returns its value.
+    fn tile_pixel(&self, band: &mut TileBand, x: u32, y: u32) -> f64 {
+        if let Some(value) = band.values[band.index(x, y)] {
+            return value;
+        }
+
+        let image_y = band.first_line + y;
+        let sample = self.sample(x as f64, image_y as f64);
+        let value = self.get_contrast_value(&sample);
+        band.set_pixel(x, y, &self.pixel(x, image_y, &sample), value);
+        if let Some(pixel_values) = band.pixel_values {
+            pixel_values.store_sample(x, image_y, sample);
+        }
+        return value;
+    }
+
+    /// Computes the color of the pixel at (`x`, `y`) where `sample` is the sample at the pixel's position.
+    /// With more than one sample per pixel, the colors of the samples are averaged.
+    fn pixel(&self, x: u32, y: u32, sample: &Sample) -> Vec<u8> {
+        if self.config.samples <= 1 {
+            return self.color(sample);
+        }
+        return self.supersample(x, y, self.config.samples, Some(sample));
+    }
+
+    /// Computes the color of the pixel at (`x`, `y`) by averaging `samples` samples spread over the pixel.
+    /// `first` is the sample at the pixel's position (the first offset) if it has already been generated.
+    fn supersample(&self, x: u32, y: u32, samples: u32, first: Option<&Sample>) -> Vec<u8> {
+        let colors: Vec<Vec<u8>> = (0..samples)
+            .map(|i| match (i, first) {
+                (0, Some(sample)) => self.color(sample),
+                _ => {
+                    let (offset_x, offset_y) = get_sample_offset(i);
+                    self.color(&self.sample(x as f64 + offset_x, y as f64 + offset_y))
+                },
+            })
+            .collect();
+        return average_colors(&colors);
+    }
+
+    /// Generates the sample at (`x`, `y`) (in pixel space) using the configured precision.
+    fn sample(&self, x: f64, y: f64) -> Sample {
+        if let Some(reference_orbit) = &self.reference_orbit {
+            return reference_orbit.method(reference_orbit.get_delta_c(x, y), self.config);
+        }
+        return match self.config.precision {
+            Precision::F32 => self.sample_with_precision::<f32>(x, y),
+            Precision::F64 => self.sample_with_precision::<f64>(x, y),
+        };
+    }
+
+    /// Generates the pixel at (`x`, `y`) with `T` as the float type.
+    fn sample_with_precision<T: GenerationFloat>(&self, x: f64, y: f64) -> Sample {
+
+        let config = self.config;
+        let math_frame = &config.math_frame;
+
+        // Sets Initial Z Value
+        let (x, y) = (T::from(x).unwrap(), T::from(y).unwrap());
+        let mut z = Complex {
+            real      : T::from(math_frame.factor_x).unwrap() * x + T::from(math_frame.shear_x).unwrap() * y + T::from(math_frame.offset_x).unwrap(),
+            imaginary : T::from(math_frame.shear_y).unwrap() * x + T::from(math_frame.factor_y).unwrap() * y + T::from(math_frame.offset_y).unwrap(),
+        };
+        let mut old_z = z;
+
+        // Sets 'c' Value (uses the initial 'c' value if set)
+        let c = match config.c_init {
+            Some(value) => value.cast::<T>(),
+            None => z,
+        };
+
+        // The derivatives of z & c with respect to the pixel position
+        let mut dz = Complex::from_real(T::one());
+        let dc = match config.c_init {
+            Some(_) => T::zero(),
+            None => T::one(),
+        };
+
+        // Some formulas start at a set value instead of the pixel position (only 'c' uses the pixel position)
+        if let (None, Some(start)) = (config.c_init, self.start) {
+            z = start.cast::<T>();
+            old_z = z;
+            dz = Complex::from_real(T::zero());
+        }
+
+        let limit = T::from(self.escape_function.get_limit(config)).unwrap();
+        let mut z_output = T::zero();
+
+        // There is no previous value before the first iteration so it starts infinitely far away
+        let mut previous_z = Complex { real: T::infinity(), imaginary: T::infinity() };
+
+        // Pixels in the main cardioid or period 2 bulb never escape so they skip iterating
+        let mut iterations = config.max_i;
+        if self.interior_checks && config.c_init.is_none() && in_main_bulbs(c) {
+            z_output = T::from(config.max_i as f64).unwrap();
+            iterations = 0;
+        }
+        let mut periodicity = match self.interior_checks {
+            true => get_periodicity_tolerance::<T>(config).map(|tolerance| Periodicity::new(z, tolerance)),
+            false => None,
+        };
+
+        // If the loop stopped before `max_i` because the escape condition was met (same as `i < maxi` on the GPU)
+        let mut stopped_early = false;
+
+        // The closest the orbit has gotten to the orbit trap
+        let mut trap = f64::INFINITY;
+
+        // Runs Math
+        for _iteration in 0..iterations {
+            if T::escaped(self.escape_function, z, previous_z, limit) {
+                stopped_early = true;
+                break;
+            }
+            previous_z = z;
+            if self.track_derivative {
+                dz = T::derivative(self.generator_function.as_ref(), c, z, dz, dc);
+            }
+            z = T::step(self.generator_function.as_ref(), c, z);
+            if let Some(orbit_trap) = &self.orbit_trap {
+                trap = trap.min(orbit_trap.distance(z.cast::<f64>()));
+            }
+
+            // Calculates Output
+            if !config.travel_distance {
+                z_output = z_output + T::one();
+            } else {
+                z_output = z_output + (
+                    (z.real - old_z.real) * (z.real - old_z.real) +
+                    (z.imaginary - old_z.imaginary) * (z.imaginary - old_z.imaginary)
+                ).sqrt();
+                old_z = z;
+            }
+
+            // Orbits which fall into a cycle never escape
+            if periodicity.as_mut().is_some_and(|v| v.is_periodic(z)) {
+                z_output = T::from(config.max_i as f64).unwrap();
+                break;
+            }
+        }
+
+        let is_inside = z_output >= T::from(config.max_i as f64).unwrap() && !config.travel_distance;
+        let (distance, normal) = match self.track_derivative && !is_inside {
+            true => Sample::get_distance_and_normal(z.cast::<f64>(), dz.cast::<f64>()),
+            false => (None, None),
+        };
+
+        return Sample {
+            z_output: z_output.to_f64().unwrap(),
+            z_norm: z.cast::<f64>().norm_sqr().sqrt(),
+            is_inside,
+            // Only pixels which met the escape condition converged to a root
+            root: match stopped_early {
+                true => self.get_closest_root(z.cast::<f64>()),
+                false => None,
+            },
+            distance,
+            normal,
+            trap: self.orbit_trap.as_ref().map(|_| trap),
+        };
+    }
+
+    /// Gets the index of the root closest to `z` (if the formula has roots.)
+    fn get_closest_root(&self, z: Complex<f64>) -> Option<usize> {
+        return self.roots
+            .iter()
+            .map(|root| (*root - z).norm_sqr())
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|v| v.0);
+    }
+
+    /// Gets the color of a generated pixel.
+    fn color(&self, sample: &Sample) -> Vec<u8> {
+        return self.painter.color(sample);
+    }
+
+}
+
+/// The values of a range of lines, kept for comparing pixels with their neighbours.
+/// The values are atomics so they can be stored from multiple threads at once.
+/// The first sample of each pixel is kept so refining the pixel doesn't generate it again.
+struct PixelValues {
+    first_line: u32,
+    end_line: u32,
+    width: u32,
+    values: Vec<AtomicU64>,
+    samples: Vec<Mutex<Option<Sample>>>,
+}
+
+impl PixelValues {
+
+    fn new(first_line: u32, end_line: u32, width: u32) -> Self {
+        return Self {
+            first_line,
+            end_line,
+            width,
+            values: (0..(end_line - first_line) as usize * width as usize)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            samples: (0..(end_line - first_line) as usize * width as usize)
+                .map(|_| Mutex::new(None))
+                .collect(),
+        };
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        return (y - self.first_line) as usize * self.width as usize + x as usize;
+    }
+
+    fn store(&self, x: u32, y: u32, value: f64) {
+        self.values[self.index(x, y)].store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    fn get(&self, x: u32, y: u32) -> f64 {
+        return f64::from_bits(self.values[self.index(x, y)].load(Ordering::Relaxed));
+    }
+
+    fn store_sample(&self, x: u32, y: u32, sample: Sample) {
+        *self.samples[self.index(x, y)].lock() = Some(sample);
+    }
+
+    fn take_sample(&self, x: u32, y: u32) -> Option<Sample> {
+        return self.samples[self.index(x, y)].lock().take();
+    }
+
+}
+
+/// A band of lines being rendered by subdivision.
+struct TileBand<'b> {
+    data: &'b mut [u8],
+    first_line: u32,
+    width: u32,
+    pixel_length: usize,
+    /// The value of each pixel in the band (`None` if it hasn't been generated yet)
+    values: Vec<Option<f64>>,
+    /// Where the samples of generated pixels are kept (for adaptive supersampling)
+    pixel_values: Option<&'b PixelValues>,
+}
+
+impl TileBand<'_> {
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        return (y * self.width + x) as usize;
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> &[u8] {
+        let start = self.index(x, y) * self.pixel_length;
+        return &self.data[start..start + self.pixel_length];
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, color: &[u8], value: f64) {
+        let index = self.index(x, y);
+        let start = index * self.pixel_length;
+        self.data[start..start + self.pixel_length].copy_from_slice(&color[0..self.pixel_length]);
+        self.values[index] = Some(value);
+    }
+
+}
+
+/// Gets the pixels on the border of the tile from (`x0`, `y0`) to (`x1`, `y1`) (inclusive.)
+fn get_tile_border(x0: u32, y0: u32, x1: u32, y1: u32) -> impl Iterator<Item = (u32, u32)> {
+    let horizontal = (x0..=x1).flat_map(move |x| [(x, y0), (x, y1)]);
+    let vertical = (y0 + 1..y1).flat_map(move |y| [(x0, y), (x1, y)]);
+    return horizontal.chain(vertical);
+}
+
+/// Gets the offset (from -0.5 to 0.5) of a sample from the position of its pixel.
+/// Samples are spread over the pixel with the R2 sequence, the first sample is at the pixel's position.
+/// The GLSL `get_sample_offset` function does the same.
+fn get_sample_offset(index: u32) -> (f64, f64) {
+    let x = (0.5 + index as f64 * 0.7548776662466927).fract() - 0.5;
+    let y = (0.5 + index as f64 * 0.5698402909980532).fract() - 0.5;
+    return (x, y);
+}
+
+/// The float types generation can be done with.
+/// Picks the object safe formula & escape methods which match the float type.
+trait GenerationFloat: Float {
+    fn step(formula: &dyn Formula, c: Complex<Self>, z: Complex<Self>) -> Complex<Self>;
+    fn derivative(formula: &dyn Formula, c: Complex<Self>, z: Complex<Self>, dz: Complex<Self>, dc: Self) -> Complex<Self>;
+    fn escaped(escape: &dyn Escape, z: Complex<Self>, previous_z: Complex<Self>, limit: Self) -> bool;
+}
+
+impl GenerationFloat for f32 {
+    fn step(formula: &dyn Formula, c: Complex<f32>, z: Complex<f32>) -> Complex<f32> {
+        formula.method_f32(c, z)
+    }
+    fn derivative(formula: &dyn Formula, c: Complex<f32>, z: Complex<f32>, dz: Complex<f32>, dc: f32) -> Complex<f32> {
+        formula.derivative_f32(c, z, dz, dc)
+    }
+    fn escaped(escape: &dyn Escape, z: Complex<f32>, previous_z: Complex<f32>, limit: f32) -> bool {
+        escape.method_f32(z, previous_z, limit)
+    }
+}
+
+impl GenerationFloat for f64 {
+    fn step(formula: &dyn Formula, c: Complex<f64>, z: Complex<f64>) -> Complex<f64> {
+        formula.method_f64(c, z)
+    }
+    fn derivative(formula: &dyn Formula, c: Complex<f64>, z: Complex<f64>, dz: Complex<f64>, dc: f64) -> Complex<f64> {
+        formula.derivative_f64(c, z, dz, dc)
+    }
+    fn escaped(escape: &dyn Escape, z: Complex<f64>, previous_z: Complex<f64>, limit: f64) -> bool {
+        escape.method_f64(z, previous_z, limit)
+    }
+}
+
+/// Function for getting the style of the CPU generation progress bar.
+pub fn get_progress_style() -> ProgressStyle {
+    return ProgressStyle::with_template("CPU Gen      [Line  {pos:>9.yellow}/{len:.red}] {bar:80.green} %{percent:3}").unwrap();
+}
+
+/// Function for getting the progress bar used for CPU generation.
+/// The bar is hidden unless logs are set to at least `INFO`.
+pub fn get_progress_bar(config: &Config, len: u64) -> ProgressBar {
+    if config.logs < Level::Info {
+        return ProgressBar::hidden();
+    }
+    return ProgressBar::new(len).with_style(get_progress_style());
+}
+
+/// Gets the amount of threads used for CPU generation.
+/// A `config.threads` of 0 uses every available core so saved configs don't depend on the machine they were made on.
+pub fn get_thread_count(config: &Config) -> u32 {
+    return match config.threads {
+        0 => default_thread_count(),
+        threads => threads,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::MathFrame;
+
+    fn get_adaptive_config(strategy: Strategy) -> Config {
+        return Config {
+            size_x: 24,
+            size_y: 24,
+            max_i: 64,
+            adaptive_samples: 4,
+            adaptive_threshold: 0.5,
+            gpu: false,
+            rgba: false,
+            threads: 2,
+            strategy,
+            subdivision_guard: true,
+            // Covers -2..2 on both axes
+            math_frame: MathFrame { factor_x: 4.0 / 24.0, factor_y: 4.0 / 24.0, shear_x: 0.0, shear_y: 0.0, offset_x: -2.0, offset_y: -2.0 },
+            ..Config::default()
+        };
+    }
+
+    fn render(renderer: &CpuRenderer, config: &Config) -> Vec<u8> {
+        let mut buf = vec![0u8; renderer.bytes_per_line() * config.size_y as usize];
+        renderer.render_lines(0, &mut buf, &ProgressBar::hidden());
+        return buf;
+    }
+
+    #[test]
+    fn refinement_reuses_the_first_sample() {
+        for strategy in [Strategy::Pixels, Strategy::Subdivide] {
+            let config = get_adaptive_config(strategy);
+            let renderer = CpuRenderer::new(&config).unwrap();
+            render(&renderer, &config);
+
+            let refined_pixels = renderer.refined_pixels.load(Ordering::Relaxed);
+            let refined_samples = renderer.refined_samples.load(Ordering::Relaxed);
+            assert!(refined_pixels > 0);
+            // Pixels filled in by subdivision don't have a first sample
+            assert!(refined_samples >= refined_pixels * 3);
+            if strategy == Strategy::Pixels {
+                assert_eq!(refined_samples, refined_pixels * 3);
+            }
+        }
+    }
+
+    #[test]
+    fn refinement_matches_supersampling() {
+        let config = get_adaptive_config(Strategy::Pixels);
+        let renderer = CpuRenderer::new(&config).unwrap();
+        let buf = render(&renderer, &config);
+
+        // Refined pixels are the same as pixels generated with every sample
+        let full = Config { samples: 4, adaptive_samples: 0, ..config.clone() };
+        let full_renderer = CpuRenderer::new(&full).unwrap();
+        for (i, pixel) in buf.chunks(3).enumerate() {
+            let (x, y) = (i as u32 % config.size_x, i as u32 / config.size_x);
+            let expected = full_renderer.supersample(x, y, 4, None);
+            assert!(pixel == &expected[0..3] || pixel == &renderer.color(&renderer.sample(x as f64, y as f64))[0..3]);
+        }
+    }
+
+    #[test]
+    fn fewer_adaptive_samples_are_skipped() {
+        let config = Config { samples: 4, ..get_adaptive_config(Strategy::Pixels) };
+        let renderer = CpuRenderer::new(&config).unwrap();
+        render(&renderer, &config);
+        assert_eq!(renderer.refined_pixels.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn tile_border() {
+        let border: Vec<(u32, u32)> = get_tile_border(0, 0, 2, 2).collect();
+        assert_eq!(border.len(), 8);
+        assert!(!border.contains(&(1, 1)));
+        for x in 0..=2 {
+            assert!(border.contains(&(x, 0)) && border.contains(&(x, 2)));
+        }
+    }
+
+    #[test]
+    fn subdivision_matches_pixels() {
+        // Without smooth coloring the inside of the set & the bands of equal iterations are filled in exactly
+        let config = Config { adaptive_samples: 0, ..get_adaptive_config(Strategy::Pixels) };
+        let pixels = render(&CpuRenderer::new(&config).unwrap(), &config);
+
+        let config = Config { strategy: Strategy::Subdivide, ..config };
+        let subdivided = render(&CpuRenderer::new(&config).unwrap(), &config);
+
+        assert_eq!(pixels, subdivided);
+    }
+}
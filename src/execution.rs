@@ -8,7 +8,7 @@ use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use minijinja::{context, Environment};
 use tokio::sync::mpsc::channel;
 use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
-use vulkano::buffer::{BufferUsage, Subbuffer};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
 use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
 use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo};
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
@@ -21,16 +21,21 @@ use vulkano::pipeline::{Pipeline, PipelineBindPoint};
 use vulkano::sync::{self, GpuFuture};
 use vulkano::VulkanError;
 
-use crate::gpu::run_glsl;
+use crate::buddhabrot::BuddhabrotRenderer;
+use crate::cpu::{get_progress_bar, CpuRenderer};
+use crate::gpu::GpuCache;
 use crate::colors::profiles::get_profile;
-use crate::structs::{Complex, Config};
+use crate::structs::Config;
 use crate::colors::color::get_color;
+use crate::colors::palette::PALETTE_SIZE;
 use crate::colors::shadows::get_shadow;
+use crate::math::escape::get_escape;
 use crate::math::formula::get_formula;
+use crate::math::interior::{get_periodicity_tolerance, use_interior_checks};
+use crate::math::orbit_trap::Trap;
+use crate::raw;
 use crate::save::get_save_method;
-use crate::structs::PushConstants;
-use crate::warn;
-use crate::Level;
+use crate::structs::{Complex, GpuSettings, Precision, PushConstants, PushConstants64};
 
 use std::error::Error;
 use std::str;
@@ -38,140 +43,196 @@ use std::sync::Arc;
 use std::time::Instant;
 use log::{self, info};
 
-use crate::gpu_thread_utils::handle_compression_thread_instructions;
+use crate::gpu_thread_utils::{get_compression_bars, handle_compression_thread_instructions};
 
 /// Function for getting image from configuration and generator function.
-pub fn cpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
+pub async fn cpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
 
     let save_method = get_save_method(config.save_method.as_str());
 
-    let color_function = get_color(&config.color_formula.as_str());
-    let shadow_function = get_shadow(&config.shadow_formula.as_str());
-    let generator_function = get_formula(&config.gen_formula.as_str());
-
-    // Sets Initial 'c' Value (If set)
-    let mut c = Complex{ real: 0f32, imaginary: 0f32, };
-    let is_julia: bool = match config.c_init {
-        Some(value) => {
-            c = value;
-            true
-        },
-        None => false,
-    };
-
-    // Sets Math Values
-    let x_math_space_factor = config.math_frame.factor_x;
-    let y_math_space_factor = config.math_frame.factor_y;
-
-    let x_math_space_offset = config.math_frame.offset_x;
-    let y_math_space_offset = config.math_frame.offset_y;
+    // Orbit densities need every orbit before any pixel is known so they can't be chunked
+    if let Some(renderer) = BuddhabrotRenderer::new(config) {
+        let img = renderer.render();
+        return save_method.method(img.as_slice(), config);
+    }
 
-    let mut z: Complex;
-    let mut old_z: Complex;
+    // Raw output stores the escape values of each pixel instead of colors
+    if save_method.get_alias() == "RAW" {
+        // Raw files aren't colored so they don't need the distribution for histogram coloring
+        let renderer = CpuRenderer::new(&Config {
+            histogram: false,
+            ..config.clone()
+        })?;
+        let mut data: Vec<u8> = vec![0; config.size_x as usize * config.size_y as usize * raw::BYTES_PER_PIXEL];
+
+        let cpu_bar = get_progress_bar(config, config.size_y as u64);
+        renderer.render_raw_lines(0, data.as_mut_slice(), &cpu_bar);
+        cpu_bar.finish();
+        renderer.log_summary();
+
+        return save_method.method(data.as_slice(), config);
+    }
 
-    let max_i = config.max_i as f64;
+    // PNG images get streamed through the compression pipeline in chunks
+    // so the whole image never has to be held in memory.
+    if save_method.get_alias() == "PNG" {
+        return cpu_eval_chunked(config).await;
+    }
 
-    let color_profile = get_profile(&config);
+    let renderer = CpuRenderer::new(config)?;
 
     // Initializes Image Buffer
-    let mut img: Vec<u8> = Vec::with_capacity(4 * (config.size_x * config.size_y) as usize);
+    let mut img: Vec<u8> = vec![0; renderer.bytes_per_line() * config.size_y as usize];
 
     // Goes through each pixel
-    for i in 0..config.size_y {
-        for j in 0..config.size_x {
-
-            // Sets Initial Z Value
-            z = Complex {
-                real      : x_math_space_factor * j as f32 + x_math_space_offset,
-                imaginary : y_math_space_factor * i as f32 + y_math_space_offset,
-            };
-            old_z = z;
-
-            if is_julia == false { c = z; }
-
-            let mut z_output: f32 = 0.0;
-
-            // Runs Math
-            for _iteration in 0..config.max_i {
-                if z.is_greater(2.0) { break }
-                z = generator_function.method(c, z);
-
-                // Calculates Output
-                if !config.travel_distance {
-                    z_output += 1.0;
-                } else {
-                    z_output += (
-                        (z.real - old_z.real) * (z.real - old_z.real) +
-                        (z.imaginary - old_z.imaginary) * (z.imaginary - old_z.imaginary)
-                    ).sqrt();
-                    old_z = z;
-                }
+    let cpu_bar = get_progress_bar(config, config.size_y as u64);
+    renderer.render_lines(0, img.as_mut_slice(), &cpu_bar);
+    cpu_bar.finish();
+    renderer.log_summary();
+
+    return save_method.method(img.as_slice(), config);
+}
+
+/// Function for generating the image on the CPU one chunk at a time.
+/// Each chunk is sent to the compression thread as soon as it is generated.
+async fn cpu_eval_chunked(config: &Config) -> Result<(), Box<dyn Error>> {
+
+    let now = Instant::now();
+
+    let renderer = CpuRenderer::new(config)?;
+    let line_length = renderer.bytes_per_line();
+
+    // Gets the amount of lines per chunk
+    let amnt_of_lines_per_chunk = config.lines_per_chunk();
+    // Gets the amount of chunks to generate
+    let generation_count = config.size_y.div_ceil(amnt_of_lines_per_chunk);
+
+    info!("Generating {} chunks...", generation_count);
+
+    // Sets up progress bar.
+    let multi_bar = MultiProgress::new();
+    let cpu_bar = multi_bar.add(get_progress_bar(config, config.size_y as u64));
+    let (compression_bar, data_write_bar) = get_compression_bars(&multi_bar, generation_count);
+
+    let (tx, rx) = channel(1);
+
+    let handle_compression_thread = tokio::spawn(handle_compression_thread_instructions(config.clone(), compression_bar, data_write_bar, generation_count, rx));
+
+    // This buffer gets reused for every chunk
+    let mut chunk_buf: Vec<u8> = vec![0; line_length * amnt_of_lines_per_chunk as usize];
+
+    for i in 0..generation_count {
+
+        let first_line = i * amnt_of_lines_per_chunk;
+        // The last chunk can have less lines than the others
+        let amnt_of_lines = amnt_of_lines_per_chunk.min(config.size_y - first_line);
+
+        let chunk = &mut chunk_buf[..line_length * amnt_of_lines as usize];
+        // Rendering blocks this thread so the compression tasks get moved to the other workers
+        tokio::task::block_in_place(|| renderer.render_lines(first_line, chunk, &cpu_bar));
+
+        tx.send({
+            // We add `amnt_of_lines` because png requires one
+            // filter byte per line.
+            let mut out_data = Vec::with_capacity(chunk.len() + amnt_of_lines as usize);
+
+            for line in chunk.chunks(line_length) {
+                out_data.push(0u8);
+                out_data.extend_from_slice(line);
             }
 
-            // Adds a pixel
-            img.extend(
-                {
-                    let out = match z_output {
-                        x if x == 0.0 => color_profile.get_background().to_owned(),
-                        x if (x >= max_i as f32 && !config.travel_distance) => color_profile.get_foreground().to_owned(),
-                        _ => color_profile.method(
-                            color_function.method(z_output as f64, &config).rem_euclid(360.0),
-                            shadow_function.method(z_output as f64).rem_euclid(360.0),
-                        ),
-                    };
-                    out[0..(3 + config.rgba as usize)].to_owned().iter()
-                }
-            );
-        }
-        if config.logs >= Level::Info {
-            print!("\t {:.2}% | {} / {}\r", 100.0 * (i as f64 + 1.0) / config.size_y as f64, i+1, config.size_y);
-        }
-    }
-    if config.logs >= Level::Info {
-        println!();
+            out_data
+        }).await.expect("Failed to send image data to compression thread!");
+
     }
 
-    return save_method.method(img.as_slice(), config);
+    cpu_bar.finish();
+    renderer.log_summary();
+
+    // We drop the TX so you can't send data anymore
+    drop(tx);
+
+    // We wait for the compressor
+    handle_compression_thread.await?;
+
+    // We display that we finished
+    log::info!("{:.2?}: Finished CPU Execution", now.elapsed());
+
+    return Ok(());
+
 }
 
 static TEMPLATE: &str = include_str!(
     concat!(env!("CARGO_MANIFEST_DIR"), "/comp.glsl")
 );
 
+/// Gets `value` as a GLSL literal of the float type used for `precision`.
+/// Double literals need the `lf` suffix to not lose precision. GLSL doesn't have literals for
+/// infinity & NaN so those are made from their bits (as formatting them would give invalid GLSL.)
+fn get_glsl_float(value: f64, precision: Precision) -> String {
+    let value = match precision {
+        Precision::F32 => value as f32 as f64,
+        Precision::F64 => value,
+    };
+    let literal = match value {
+        v if v.is_nan() => "uintBitsToFloat(0x7FC00000u)".to_string(),
+        v if v == f64::INFINITY => "uintBitsToFloat(0x7F800000u)".to_string(),
+        v if v == f64::NEG_INFINITY => "uintBitsToFloat(0xFF800000u)".to_string(),
+        v => return match precision {
+            Precision::F32 => format!("{:?}", v as f32),
+            Precision::F64 => format!("{:?}lf", v),
+        },
+    };
+    return match precision {
+        Precision::F32 => literal,
+        Precision::F64 => format!("double({})", literal),
+    };
+}
+
 pub async fn gpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
+    return gpu_eval_with_cache(config, &mut GpuCache::default()).await;
+}
 
-    /// Takes a Vec<f64> and returns a string that looks like 1.00000, 2.00000, 3.00000
-    /// Returns Option<None> if the result isn't the expected length
-    fn get_arr_str_with_len(in_arr: Vec<f64>, expected_length: usize) -> Option<String> {
-        if in_arr.len() != expected_length {
-            log::debug!("Invalid length of array.");
-            log::debug!("Expected length: `{expected_length}`.");
-            log::debug!("Got Array: `{in_arr:?}`.");
-            return None;
-        }
-        return Some(in_arr
-            .iter()
-            .map(|v| format!("{v:.5?}"))
-            .collect::<Vec<String>>()
-            .join(", ")
-            .into()
-        );
-    }
+/// Function for generating the image on the GPU with the device & pipelines in `cache`.
+/// The pipeline is only compiled if no earlier image in the cache used the same shader.
+pub async fn gpu_eval_with_cache(config: &Config, cache: &mut GpuCache) -> Result<(), Box<dyn Error>> {
 
     let color_function = get_color(&config.color_formula.as_str());
     let shadow_function = get_shadow(&config.shadow_formula.as_str());
-    let generator_function = get_formula(&config.gen_formula.as_str());
+    let generator_function = get_formula(&config);
+    let escape_function = get_escape(&config);
 
-    // Sets value for math constant 'c'
-    let c: [f64; 2] = match config.c_init {
-        Some(value) => {
-            [value.real as f64, value.imaginary as f64]
-        },
-        None => [0.0, 0.0],
+    let now = Instant::now();
+
+    // The device decides which precision can actually be used
+    let (device, queue, precision) = cache.get_device(&now, config.precision)?;
+    let config = &Config {
+        precision,
+        ..config.clone()
     };
 
+    // Sets value for math constant 'c'
+    let c = config.c_init.unwrap_or(Complex { real: 0.0, imaginary: 0.0 });
+
     let _color_profile = get_profile(&config);
 
+    // Histogram coloring needs the distribution of the whole image before the first chunk is colored
+    // so it gets collected from a preview generated on the CPU.
+    let histogram = match config.histogram {
+        true => CpuRenderer::new(config)?.get_histogram().cloned(),
+        false => None,
+    };
+    let roots = generator_function.get_roots()?;
+    let orbit_trap = match &config.orbit_trap {
+        Some(settings) => Some(Trap::new(settings)?),
+        None => None,
+    };
+    let interior_checks = use_interior_checks(&config, generator_function.as_ref(), escape_function);
+    let vec2 = match config.precision {
+        Precision::F32 => "vec2",
+        Precision::F64 => "dvec2",
+    };
+
     let compiled_shader = {
         let mut env = Environment::new();
         env.add_template(
@@ -183,20 +244,52 @@ pub async fn gpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
         // Big mess that passes all the values to the Jinja template
         compute_shader.render(context!(
             formula => generator_function.gpu_method(),
-            width => config.size_x,
-            height => config.size_y,
+            derivative => generator_function.gpu_derivative(),
+            track_derivative => config.distance_estimation || shadow_function.needs_derivative(),
+            derivative_c => match config.c_init {
+                Some(_) => "0.0", // Is julia settings
+                None => "1.0", // Mandelbrot settings
+            },
+            derivative_step => match config.precision {
+                Precision::F32 => get_glsl_float(f32::EPSILON.sqrt() as f64, config.precision),
+                Precision::F64 => get_glsl_float(f64::EPSILON.sqrt(), config.precision),
+            },
+            distance_estimation => format!("{:?}", config.distance_estimation),
             travel_distance => format!("{:?}", config.travel_distance),
-            rate_of_color_change => format!("{:.1}", config.rate_of_color_change),
-            background => get_arr_str_with_len(config.background.to_array().into(), 4).unwrap(),
-            foreground => get_arr_str_with_len(config.foreground.to_array().into(), 4).unwrap(),
-            max_i => format!("{:}", config.max_i),
-            c_init => get_arr_str_with_len(c.into(), 2).unwrap(),
+            smooth => format!("{:?}", config.smooth),
+            escape => escape_function.gpu_method(),
+            interior_checks => interior_checks,
+            bulb_check => interior_checks && config.c_init.is_none(),
+            float => match config.precision {
+                Precision::F32 => "float",
+                Precision::F64 => "double",
+            },
+            vec2 => vec2,
             colors => color_function.gpu_method(),
             shadows => shadow_function.gpu_method(),
-            julia_changes => match config.c_init {
-                Some(_) => "", // Is julia settings
-                None => "c = z;", // Mandelbrot settings
-            }
+            julia_changes => match (config.c_init, generator_function.get_start()) {
+                (Some(_), _) => "".to_string(), // Is julia settings
+                (None, None) => "c = z;".to_string(), // Mandelbrot settings
+                // Mandelbrot settings for formulas which start at a set value
+                (None, Some(start)) => format!(
+                    "c = z;\n    z = Complex({}({}, {}));\n    dz = number(0.0);",
+                    vec2,
+                    get_glsl_float(start.real, config.precision),
+                    get_glsl_float(start.imaginary, config.precision),
+                ),
+            },
+            palette => config.palette.is_some(),
+            palette_size => PALETTE_SIZE,
+            histogram => histogram.is_some(),
+            orbit_trap => orbit_trap.is_some(),
+            trap_method => orbit_trap.as_ref().map(|v| v.gpu_method()).unwrap_or_default(),
+            root_colors => color_function.gpu_root_method(),
+            root_count => roots.len(),
+            roots => roots
+                .iter()
+                .map(|v| format!("{}({}, {})", vec2, get_glsl_float(v.real, config.precision), get_glsl_float(v.imaginary, config.precision)))
+                .collect::<Vec<String>>()
+                .join(", "),
             ))
             .unwrap()
             .replace("\\n", "\n")
@@ -206,9 +299,7 @@ pub async fn gpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
 
     log::debug!("{}", compiled_shader);
 
-    let now = Instant::now();
-
-    let (device, pipeline, mut queues) = run_glsl(&now, compiled_shader)?;
+    let pipeline = cache.get_pipeline(&now, device.clone(), compiled_shader, config.precision)?;
 
     let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
 
@@ -236,12 +327,16 @@ pub async fn gpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
 
     let layout = &pipeline.layout().set_layouts();
 
-    let queue = queues.next().unwrap();
     let queue_family_index = device.active_queue_family_indices().first().ok_or(VulkanError::InitializationFailed)?.clone();
 
+    // Each chunk is a `size_x` x `amnt_of_lines_per_chunk` image which has to fit in the device's limits
+    let max_image_size = device.physical_device().properties().max_image_dimension2_d;
+    if config.size_x > max_image_size {
+        return Err(format!("The width of the image ({}px) is larger than the GPU supports ({}px)!", config.size_x, max_image_size).into());
+    }
+
     // Gets the amount of lines per chunk
-    // Uses higher precision for inner calculation because of overflow worries.
-    let mut amnt_of_lines_per_chunk = (config.chunk_size.pow(2) / config.size_x as u64) as u32;
+    let mut amnt_of_lines_per_chunk = config.lines_per_chunk().min(max_image_size);
     // Gets the amount of chunks to generate
     let generation_count = (config.size_y).div_ceil(amnt_of_lines_per_chunk);
 
@@ -249,14 +344,14 @@ pub async fn gpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
     // MUST be recalculated if `amnt_of_lines_per_chunk` changes.
     let mut buf_length = (config.size_x * amnt_of_lines_per_chunk * 4) as usize;
     // The size of each chunk in bytes
-    let image_buf_length = config.chunk_size.pow(2) * 4;
+    let image_buf_length = buf_length as u64;
 
     let image = Image::new(
         memory_allocator.clone(),
         ImageCreateInfo {
             image_type: ImageType::Dim2d,
             format: Format::R8G8B8A8_UNORM,
-            extent: [config.chunk_size as u32, config.chunk_size as u32, 1],
+            extent: [config.size_x, amnt_of_lines_per_chunk, 1],
             usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
             ..Default::default()
         },
@@ -270,45 +365,121 @@ pub async fn gpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
 
     let view = ImageView::new_default(image.clone())?;
 
+    // The shader looks palette colors up in the same table as the CPU
+    let palette_table = match &config.palette {
+        Some(palette) => palette.get_table(),
+        None => vec![0],
+    };
+    let palette_buffer = Buffer::from_iter(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        palette_table,
+    )?;
+
+    // Image orbit traps look the darkness of the image up in this buffer
+    let trap_image_buffer = Buffer::from_iter(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        orbit_trap.as_ref().map_or(vec![1.0], |v| v.get_image_values()),
+    )?;
+
+    // The values which don't change the shader are passed in a buffer so the pipeline can be reused
+    let settings_buffer = Buffer::from_data(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        GpuSettings {
+            background: config.background.to_array().map(|v| v as f32),
+            foreground: config.foreground.to_array().map(|v| v as f32),
+            trap_direction: orbit_trap.as_ref().map_or([1.0, 0.0], |v| {
+                let direction = v.get_direction();
+                [direction.real as f32, direction.imaginary as f32]
+            }),
+            trap_radius: config.orbit_trap.as_ref().map_or(1.0, |v| v.radius as f32),
+            escape_limit: escape_function.get_limit(config) as f32,
+            boundary_width: config.boundary_width as f32,
+            light_angle: config.light_angle as f32,
+            light_height: config.light_height as f32,
+            rate_of_color_change: config.rate_of_color_change as f32,
+            color_cycle: (360.0 / config.rate_of_color_change) as f32,
+            width: config.size_x,
+            max_i: config.max_i.min(i32::MAX as u64) as u32,
+            samples: config.samples,
+        },
+    )?;
+
+    // Histogram coloring looks the distribution up in this buffer
+    let histogram_buffer = Buffer::from_iter(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        histogram.as_ref().map_or(vec![0.0], |v| v.get_quantiles().iter().map(|q| *q as f32).collect()),
+    )?;
+
     // Here we setup the descriptor sets.
     let image_desc_set = PersistentDescriptorSet::new(
         &descriptor_set_allocator,
         layout[0].clone(),
-        [WriteDescriptorSet::image_view(0, view.clone())],
+        [
+            WriteDescriptorSet::image_view(0, view.clone()),
+            WriteDescriptorSet::buffer(1, palette_buffer.clone()),
+            WriteDescriptorSet::buffer(2, trap_image_buffer.clone()),
+            WriteDescriptorSet::buffer(3, settings_buffer.clone()),
+            WriteDescriptorSet::buffer(4, histogram_buffer.clone()),
+        ],
         [],
         )?;
 
-    let mut push_constants = PushConstants {
-        factor_x: config.math_frame.factor_x * config.size_x as f32,
-        factor_y: config.math_frame.factor_y * config.size_y as f32,
-        offset_x: config.math_frame.offset_x,
-        offset_y: config.math_frame.offset_y,
-        amnt_of_lines: amnt_of_lines_per_chunk,
+    // Values for the push constants.
+    // These get converted to the configured precision for each chunk.
+    // The x values are across the whole line & the y values are per line (scaled by the lines of each chunk.)
+    let factor_x = config.math_frame.factor_x * config.size_x as f64;
+    let shear_y = config.math_frame.shear_y * config.size_x as f64;
+    let mut offset_x = config.math_frame.offset_x;
+    let mut offset_y = config.math_frame.offset_y;
+    let amnt_of_lines = amnt_of_lines_per_chunk;
+    let trap_center = config.orbit_trap.as_ref().map_or(Complex { real: 0.0, imaginary: 0.0 }, |v| v.center);
+    // A tolerance of 0 never matches so the periodicity check gets skipped
+    let periodicity_tolerance = match config.precision {
+        Precision::F32 => get_periodicity_tolerance::<f32>(config).unwrap_or(0.0) as f64,
+        Precision::F64 => get_periodicity_tolerance::<f64>(config).unwrap_or(0.0),
     };
 
-    let original_factor_y = push_constants.factor_y;
-
     info!("Generating {} chunks...", generation_count);
 
-    let dispatch_size = config.chunk_size.div_ceil(16) as u32;
-    if dispatch_size as u64 != config.chunk_size / 16 {
-        warn!("Configured chunk size ({0}x{0}) overwrites some data. This will work fine however an optimal chunk size would be a multiple of 16!", config.chunk_size);
-    }
-
     // Sets up progress bar.
     let gpu_bar_style         = ProgressStyle::with_template("GPU Gen      [Chunk {pos:>9.yellow}/{len:.red}] {bar:80.green} %{percent:3}").unwrap();
     let mut gpu_bar = ProgressBar::new(generation_count as u64).with_style(gpu_bar_style);
 
-    let compression_bar_style = ProgressStyle::with_template("Compression  [Chunk {pos:>9.yellow}/{len:.red}] {bar:80.green} %{percent:3}").unwrap();
-    let mut compression_bar = ProgressBar::new(generation_count as u64).with_style(compression_bar_style);
-
-    let data_write_bar_style  = ProgressStyle::with_template("Data Written [Chunk {pos:>9.yellow}/{len:.red}] {bar:80.green} %{percent:3}  eta: {eta:.green}  {spinner}").unwrap();
-    let mut data_write_bar = ProgressBar::new(generation_count as u64).with_style(data_write_bar_style);
-
     let multi_bar = MultiProgress::new();
     gpu_bar = multi_bar.add(gpu_bar);
-    compression_bar = multi_bar.add(compression_bar);
-    data_write_bar = multi_bar.add(data_write_bar);
+    let (compression_bar, data_write_bar) = get_compression_bars(&multi_bar, generation_count);
 
     let (tx, rx) = channel(1);
 
@@ -316,7 +487,8 @@ pub async fn gpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
 
     for i in 0..generation_count {
 
-        push_constants.factor_y = original_factor_y * amnt_of_lines_per_chunk as f32 / config.size_y as f32;
+        let factor_y = config.math_frame.factor_y * amnt_of_lines_per_chunk as f64;
+        let shear_x = config.math_frame.shear_x * amnt_of_lines_per_chunk as f64;
 
         if i == generation_count - 1 && i != 0 {
             // We can reassign becaues this should be the last iteration
@@ -342,9 +514,42 @@ pub async fn gpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
                 pipeline.layout().clone(),
                 0,
                 image_desc_set.clone(),
-            )?
-            .push_constants(pipeline.layout().clone(), 0, push_constants.clone())?
-            .dispatch([dispatch_size, dispatch_size, 1])?
+            )?;
+
+        match config.precision {
+            Precision::F32 => builder.push_constants(pipeline.layout().clone(), 0, PushConstants {
+                factor_x: factor_x as f32,
+                factor_y: factor_y as f32,
+                shear_x: shear_x as f32,
+                shear_y: shear_y as f32,
+                offset_x: offset_x as f32,
+                offset_y: offset_y as f32,
+                amnt_of_lines,
+                c_real: c.real as f32,
+                c_imaginary: c.imaginary as f32,
+                trap_center_real: trap_center.real as f32,
+                trap_center_imaginary: trap_center.imaginary as f32,
+                periodicity_tolerance: periodicity_tolerance as f32,
+            })?,
+            Precision::F64 => builder.push_constants(pipeline.layout().clone(), 0, PushConstants64 {
+                factor_x,
+                factor_y,
+                shear_x,
+                shear_y,
+                offset_x,
+                offset_y,
+                amnt_of_lines,
+                _padding: 0,
+                c_real: c.real,
+                c_imaginary: c.imaginary,
+                trap_center_real: trap_center.real,
+                trap_center_imaginary: trap_center.imaginary,
+                periodicity_tolerance,
+            })?,
+        };
+
+        builder
+            .dispatch([config.size_x.div_ceil(16), amnt_of_lines_per_chunk.div_ceil(16), 1])?
             .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
                 image.clone(),
                 data_buffer.clone(),
@@ -389,7 +594,8 @@ pub async fn gpu_eval(config: &Config) -> Result<(), Box<dyn Error>> {
         gpu_bar = gpu_bar.with_elapsed(elapsed);
         gpu_bar.inc(1);
 
-        push_constants.offset_y += original_factor_y * amnt_of_lines_per_chunk as f32 / config.size_y as f32;
+        offset_x += config.math_frame.shear_x * amnt_of_lines_per_chunk as f64;
+        offset_y += config.math_frame.factor_y * amnt_of_lines_per_chunk as f64;
 
     }
 
@@ -2,17 +2,17 @@ extern crate vulkano;
 extern crate image;
 extern crate shaderc;
 
-use crate::structs::PushConstants;
+use crate::structs::{Precision, PushConstants, PushConstants64};
 
 use ahash::HashMapExt;
 use shaderc::CompilationArtifact;
 use core::panic;
 use std::{
-    collections::HashSet, error::Error, time::Instant
+    collections::{HashMap, HashSet}, error::Error, time::Instant
 };
 
 use vulkano::{
-    descriptor_set::layout::{DescriptorSetLayout, DescriptorSetLayoutCreateFlags, DescriptorSetLayoutCreateInfo, DescriptorType}, device::{
+    descriptor_set::layout::{DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateFlags, DescriptorSetLayoutCreateInfo, DescriptorType}, device::{
         physical::PhysicalDeviceType, Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags
     }, instance::{
         Instance,
@@ -79,7 +79,10 @@ fn compile_to_spirv(glsl: String, kind: shaderc::ShaderKind, entry_point_name: &
     }
 }
 
-pub fn run_glsl(now: &Instant, glsl: String) -> Result<(Arc<Device>, Arc<ComputePipeline>, impl ExactSizeIterator<Item = Arc<Queue>>), Box<dyn Error>> {
+/// Function for creating the device used for generation.
+/// If `Precision::F64` is requested but the device doesn't support `shader_float64`, this falls
+/// back to `Precision::F32`. The returned precision is the one the shader should be compiled with.
+pub fn get_device(now: &Instant, precision: Precision) -> Result<(Arc<Device>, impl ExactSizeIterator<Item = Arc<Queue>>, Precision), Box<dyn Error>> {
 
     // Boilerplate Initialization
     let library = match VulkanLibrary::new() {
@@ -134,10 +137,25 @@ pub fn run_glsl(now: &Instant, glsl: String) -> Result<(Arc<Device>, Arc<Compute
         return Err(Box::new(VulkanError::FeatureNotPresent));
     }
 
+    // Enables doubles in shaders if they are needed (and supported)
+    let mut enabled_features = Features::empty();
+    let precision = match precision {
+        Precision::F64 if physical_device.supported_features().shader_float64 => {
+            enabled_features.shader_float64 = true;
+            Precision::F64
+        },
+        Precision::F64 => {
+            log::warn!("Device doesn't support `shader_float64`, falling back to f32 precision!");
+            Precision::F32
+        },
+        Precision::F32 => Precision::F32,
+    };
+
     let (device, queues) = Device::new(
         physical_device,
         DeviceCreateInfo {
             enabled_extensions: device_extensions,
+            enabled_features,
             queue_create_infos: vec![QueueCreateInfo {
                 queue_family_index,
                 ..Default::default()
@@ -146,6 +164,14 @@ pub fn run_glsl(now: &Instant, glsl: String) -> Result<(Arc<Device>, Arc<Compute
         }
     )?;
 
+    return Ok((device, queues, precision));
+
+}
+
+/// Function for compiling `glsl` into the compute pipeline used for generation.
+/// `precision` decides the layout of the push constants.
+pub fn run_glsl(now: &Instant, device: Arc<Device>, glsl: String, precision: Precision) -> Result<Arc<ComputePipeline>, Box<dyn Error>> {
+
     let entry_point = "main";
 
     let cs = {
@@ -163,6 +189,11 @@ pub fn run_glsl(now: &Instant, glsl: String) -> Result<(Arc<Device>, Arc<Compute
         }
     };
 
+    let push_constants_size = match precision {
+        Precision::F32 => std::mem::size_of::<PushConstants>(),
+        Precision::F64 => std::mem::size_of::<PushConstants64>(),
+    };
+
     let pipeline = {
 
         let stage = PipelineShaderStageCreateInfo::new(
@@ -192,7 +223,27 @@ pub fn run_glsl(now: &Instant, glsl: String) -> Result<(Arc<Device>, Arc<Compute
                             map
                         },
                         ..Default::default()
-                    }).into())].into(),
+                    }).into()),
+                    // The table of palette colors (a single unused color if a palette isn't set)
+                    (1, DescriptorSetLayoutBinding {
+                        stages: ShaderStages::COMPUTE,
+                        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
+                    }),
+                    // The image of image orbit traps (a single unused value for other traps)
+                    (2, DescriptorSetLayoutBinding {
+                        stages: ShaderStages::COMPUTE,
+                        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
+                    }),
+                    // The settings which don't change the shader (`GpuSettings`)
+                    (3, DescriptorSetLayoutBinding {
+                        stages: ShaderStages::COMPUTE,
+                        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
+                    }),
+                    // The distribution used by histogram coloring (a single unused value without it)
+                    (4, DescriptorSetLayoutBinding {
+                        stages: ShaderStages::COMPUTE,
+                        ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
+                    })].into(),
                     ..Default::default()
                 })?,
             ],
@@ -200,7 +251,7 @@ pub fn run_glsl(now: &Instant, glsl: String) -> Result<(Arc<Device>, Arc<Compute
                 PushConstantRange {
                     stages: ShaderStages::COMPUTE,
                     offset: 0,
-                    size: std::mem::size_of::<PushConstants>() as u32,
+                    size: push_constants_size as u32,
                 }
             ],
             ..Default::default()
@@ -215,6 +266,52 @@ pub fn run_glsl(now: &Instant, glsl: String) -> Result<(Arc<Device>, Arc<Compute
 
     log::info!("{:.2?}: Compiled Shaders", now.elapsed());
 
-    return Ok((device, pipeline, queues));
+    return Ok(pipeline);
+
+}
+
+/// The device & compiled pipelines kept between generations.
+/// Batches of images reuse these so the GPU is only set up once & images whose shaders are the same
+/// only get compiled once. The shader only depends on the formula & which coloring features are used,
+/// values like the size, iterations, colors & julia constant are passed in push constants & the
+/// settings buffer (see `GpuSettings`) so images which only differ in them share a pipeline.
+#[derive(Default)]
+pub struct GpuCache {
+    /// The requested precision, the device, its queue & the precision it supports.
+    device: Option<(Precision, Arc<Device>, Arc<Queue>, Precision)>,
+    /// The pipelines which have been compiled by their shader source.
+    pipelines: HashMap<String, Arc<ComputePipeline>>,
+}
+
+impl GpuCache {
+
+    /// Gets the device for `precision` (see `get_device`), creating it if it doesn't exist yet.
+    pub fn get_device(&mut self, now: &Instant, precision: Precision) -> Result<(Arc<Device>, Arc<Queue>, Precision), Box<dyn Error>> {
+        if let Some((requested, device, queue, supported)) = &self.device {
+            if *requested == precision {
+                return Ok((device.clone(), queue.clone(), *supported));
+            }
+        }
+
+        let (device, mut queues, supported) = get_device(now, precision)?;
+        let queue = queues.next().ok_or(VulkanError::InitializationFailed)?;
+
+        // Pipelines belong to the device they were compiled on
+        self.pipelines.clear();
+        self.device = Some((precision, device.clone(), queue.clone(), supported));
+        return Ok((device, queue, supported));
+    }
+
+    /// Gets the pipeline for `glsl` (see `run_glsl`), only compiling it if it hasn't been compiled before.
+    pub fn get_pipeline(&mut self, now: &Instant, device: Arc<Device>, glsl: String, precision: Precision) -> Result<Arc<ComputePipeline>, Box<dyn Error>> {
+        if let Some(pipeline) = self.pipelines.get(&glsl) {
+            log::info!("{:.2?}: Reused Compiled Shaders", now.elapsed());
+            return Ok(pipeline.clone());
+        }
+
+        let pipeline = run_glsl(now, device, glsl.clone(), precision)?;
+        self.pipelines.insert(glsl, pipeline.clone());
+        return Ok(pipeline);
+    }
 
 }
@@ -1,7 +1,7 @@
 use gzp::deflate::Zlib;
 use gzp::par::compress::{ParCompress, ParCompressBuilder};
 use gzp::{Compression, ZWriter};
-use indicatif::ProgressBar;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use png::chunk::IDAT;
 use png::Filter;
 use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, UnboundedReceiver};
@@ -14,6 +14,22 @@ use std::io::{BufWriter, Write};
 use std::path::Path;
 use log::{self, debug, info};
 
+/// Function for adding the compression & data writing progress bars to `multi_bar`.
+pub fn get_compression_bars(multi_bar: &MultiProgress, generation_count: u32) -> (ProgressBar, ProgressBar) {
+
+    let compression_bar_style = ProgressStyle::with_template("Compression  [Chunk {pos:>9.yellow}/{len:.red}] {bar:80.green} %{percent:3}").unwrap();
+    let compression_bar = ProgressBar::new(generation_count as u64).with_style(compression_bar_style);
+
+    let data_write_bar_style  = ProgressStyle::with_template("Data Written [Chunk {pos:>9.yellow}/{len:.red}] {bar:80.green} %{percent:3}  eta: {eta:.green}  {spinner}").unwrap();
+    let data_write_bar = ProgressBar::new(generation_count as u64).with_style(data_write_bar_style);
+
+    return (
+        multi_bar.add(compression_bar),
+        multi_bar.add(data_write_bar),
+    );
+
+}
+
 /// Method for running on the chunk writing thread.
 pub async fn handle_compression_thread_instructions(config: Config, mut compression_bar: ProgressBar, data_write_bar: ProgressBar, generation_count: u32, mut rx: Receiver<Vec<u8>>) -> Option<()> {
 
@@ -68,14 +84,20 @@ pub async fn handle_data_thread_instructions(config: Config, mut data_write_bar:
     let file = File::create(path).expect(&format!("Failed to create file..? Filename: `{}`", config.filename));
     let ref mut w = BufWriter::new(file);
 
+    // The GPU always generates RGBA data while the CPU can also generate RGB data.
+    let color_type = match config.rgba {
+        true => png::ColorType::Rgba,
+        false => png::ColorType::Rgb,
+    };
+
     // Here we add info to the file
     let mut info = png::Info::with_size(config.size_x, config.size_y);
     info.bit_depth = png::BitDepth::Eight;
-    info.color_type = png::ColorType::Rgba;
+    info.color_type = color_type;
 
     // Here we setup the encoder for the image
     let mut encoder = png::Encoder::with_info(w, info.clone()).ok()?;
-    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_color(color_type);
     encoder.set_depth(png::BitDepth::Eight);
     encoder.set_compression(png::Compression::High);
     encoder.set_filter(Filter::NoFilter);
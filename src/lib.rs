@@ -16,6 +16,20 @@ pub mod execution;
 pub mod cli;
 /// Module for handling various save methods.
 pub mod save;
+/// Module for the raw output format used for recoloring.
+pub mod raw;
+/// Module for regenerating images from their saved configs.
+pub mod reproduce;
+/// Module for reading & writing TOML & JSON config files.
+pub mod config_file;
+/// Module for overriding loaded configs with CLI flags.
+pub mod overrides;
+/// Module for rendering batches of images from a manifest.
+pub mod batch;
+/// Module for the multithreaded CPU renderer.
+pub mod cpu;
+/// Module for orbit density (Buddhabrot) rendering.
+pub mod buddhabrot;
 /// Module for setting up GPU execution.
 pub mod gpu;
 /// Module for handling the various threads used in GPU execution.
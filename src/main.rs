@@ -8,18 +8,22 @@ Author : Mark T
 // std imports
 use std::env;
 use std::fs::File;
+use std::path::Path;
 use std::time::Instant;
 
 // External Crates
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
 use png::Decoder;
 
 pub use kyros::*;
 
-pub use kyros::structs::{Complex, Config, MathFrame};
-pub use kyros::cli::Args;
+pub use kyros::structs::{Buddhabrot, Complex, Config, DeepZoom, MathFrame, OrbitTrap, View};
+pub use kyros::cli::{Args, Command};
 
 pub use kyros::colors::color::get_color;
+pub use kyros::colors::palette::Palette;
 pub use kyros::colors::shadows::get_shadow;
 pub use kyros::math::formula::get_formula;
 pub use kyros::save::get_save_method;
@@ -42,55 +46,218 @@ impl log::Log for Logger {
     fn flush(&self) {}
 }
 
-/// Main function of the program
-#[tokio::main]
-async fn main() {
-
-    log::set_logger(&LOGGER).unwrap();
-
-    // env::set_var("RUST_BACKTRACE", "1");
-    env::set_var("RUST_BACKTRACE", "full");
-
-    // Defines values from CLI arguments
-    let cli_args = Args::parse();
+/// Parses the real or imaginary value of the center of the view.
+fn parse_center(value: &str) -> f64 {
+    return match value.trim().parse::<f64>() {
+        Ok(v) if v.is_finite() => v,
+        _ => Args::command().error(
+            ErrorKind::InvalidValue,
+            format!("Can't parse '{}' as a decimal number!", value),
+        ).exit(),
+    };
+}
 
-    let mut config = Config {
-        c_init: None,
+/// Creates the config set by the CLI arguments.
+fn build_config(cli_args: &Args) -> Config {
+
+    // The image size flags override --pixels
+    let (size_x, size_y) = cli_args.size.unwrap_or((
+        cli_args.width.unwrap_or(cli_args.pixels),
+        cli_args.height.unwrap_or(cli_args.pixels),
+    ));
+
+    // The view flags place the image by its center instead of its factors & offsets
+    let uses_view = cli_args.center_real.is_some()
+        || cli_args.center_imaginary.is_some()
+        || cli_args.radius.is_some()
+        || cli_args.zoom.is_some()
+        || cli_args.rotation.is_some();
+    let center_real = cli_args.center_real.clone().unwrap_or("-0.5".to_string());
+    let center_imaginary = cli_args.center_imaginary.clone().unwrap_or("0".to_string());
+    let radius = cli_args.radius.or(cli_args.zoom.map(|zoom| 2.0 / zoom)).unwrap_or(2.0);
+    let rotation = cli_args.rotation.unwrap_or(0.0);
+
+    return Config {
+        c_init: match cli_args.julia {
+            true => Some(Complex {
+                real: cli_args.c_real,
+                imaginary: cli_args.c_imaginary,
+            }),
+            false => None,
+        },
 
-        size_x: cli_args.pixels,
-        size_y: cli_args.pixels,
+        size_x,
+        size_y,
 
         max_i: cli_args.iterations,
-
-        gen_formula: cli_args.formula,
-        color_formula: cli_args.color,
+        samples: cli_args.samples,
+        adaptive_samples: cli_args.adaptive_samples,
+        adaptive_threshold: cli_args.adaptive_threshold,
+
+        gen_formula: cli_args.formula.clone(),
+        power: cli_args.power,
+        polynomial: cli_args.polynomial.clone(),
+        color_formula: cli_args.color.clone(),
         rate_of_color_change: cli_args.rate_of_color_change,
-        shadow_formula: cli_args.shadow,
+        shadow_formula: cli_args.shadow.clone(),
+        palette: cli_args.palette.as_ref().map(|stops| {
+            Palette::from_arg(stops, cli_args.palette_space)
+                .unwrap_or_else(|e| Args::command().error(ErrorKind::InvalidValue, e).exit())
+        }),
 
         background: csscolorparser::parse(&cli_args.background.as_str()).unwrap(),
         foreground: csscolorparser::parse(&cli_args.foreground.as_str()).unwrap(),
 
         // rgba: cli_args.rgba,
         travel_distance: cli_args.travel_distance,
-
-        save_method: cli_args.save_method,
-        filename: cli_args.filename,
+        smooth: cli_args.smooth,
+        histogram: cli_args.histogram,
+        bailout: cli_args.bailout,
+        escape_formula: cli_args.escape.clone(),
+        tolerance: cli_args.tolerance,
+        distance_estimation: cli_args.distance_estimation,
+        boundary_width: cli_args.boundary_width,
+        light_angle: cli_args.light_angle,
+        light_height: cli_args.light_height,
+
+        save_method: cli_args.save_method.clone(),
+        filename: cli_args.filename.clone(),
 
         rgba: cli_args.rgba | cli_args.gpu, // forces rgba if using gpu
         gpu: cli_args.gpu,
-        read_config: cli_args.read_config,
-        chunk_size: cli_args.chunk_size.unwrap_or(cli_args.pixels as u64),
+        read_config: cli_args.read_config.clone(),
+        chunk_size: cli_args.chunk_size.unwrap_or(size_x.max(size_y) as u64),
         compression: cli_args.compression,
         compression_threads: cli_args.compression_threads,
-
-        math_frame: MathFrame {
-            factor_x: (cli_args.factor_x / (cli_args.pixels as f64 - 1.0)) as f32,
-            factor_y: (cli_args.factor_y / (cli_args.pixels as f64 - 1.0)) as f32,
-            offset_x: cli_args.offset_x as f32,
-            offset_y: cli_args.offset_y as f32,
+        threads: cli_args.threads,
+        precision: cli_args.precision,
+        strategy: cli_args.strategy,
+        subdivision_guard: cli_args.subdivision_guard,
+        deep_zoom: match cli_args.deep_zoom {
+            true => Some(DeepZoom {
+                center_real: center_real.clone(),
+                center_imaginary: center_imaginary.clone(),
+                radius,
+                rotation,
+            }),
+            false => None,
         },
+        buddhabrot: match cli_args.buddhabrot || cli_args.anti_buddhabrot {
+            true => Some(Buddhabrot {
+                orbits: cli_args.orbits,
+                channel_max_i: match &cli_args.nebula {
+                    Some(limits) => [limits[0], limits[1], limits[2]],
+                    None => [cli_args.iterations; 3],
+                },
+                anti: cli_args.anti_buddhabrot,
+                seed: cli_args.seed,
+                gamma: cli_args.density_gamma,
+            }),
+            false => None,
+        },
+        view: match uses_view && !cli_args.deep_zoom {
+            true => Some(View {
+                center: Complex {
+                    real: parse_center(&center_real),
+                    imaginary: parse_center(&center_imaginary),
+                },
+                radius,
+                rotation,
+            }),
+            false => None,
+        },
+        orbit_trap: cli_args.orbit_trap.map(|shape| OrbitTrap {
+            shape,
+            center: Complex {
+                real: cli_args.trap_real,
+                imaginary: cli_args.trap_imaginary,
+            },
+            angle: cli_args.trap_angle,
+            radius: cli_args.trap_radius,
+            image: cli_args.trap_image.clone(),
+        }),
+
+        math_frame: MathFrame::fit(
+            cli_args.factor_x,
+            cli_args.factor_y,
+            cli_args.offset_x,
+            cli_args.offset_y,
+            size_x,
+            size_y,
+            cli_args.stretch,
+        ),
         logs: cli_args.logs,
     };
+}
+
+/// Main function of the program
+#[tokio::main]
+async fn main() {
+
+    log::set_logger(&LOGGER).unwrap();
+
+    // env::set_var("RUST_BACKTRACE", "1");
+    env::set_var("RUST_BACKTRACE", "full");
+
+    // Defines values from CLI arguments
+    let matches = Args::command().get_matches();
+    let cli_args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let mut config = build_config(&cli_args);
+    let is_set = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+    // Loads a config file, flags given on the command line override its settings
+    if let Some(filename) = &cli_args.config {
+        let mut file_config = match config_file::read_config_file(filename, &config) {
+            Ok(v) => v,
+            Err(e) => Args::command().error(ErrorKind::InvalidValue, e.to_string()).exit(),
+        };
+        overrides::apply_overrides(&mut file_config, &config, &cli_args, is_set);
+        config = file_config;
+    }
+
+    // Renders every job in a manifest instead of a single image
+    if let Some(Command::Batch(batch_args)) = &cli_args.command {
+        if cli_args.reproduce.is_some() {
+            Args::command().error(ErrorKind::ArgumentConflict, "--reproduce can't be used with the batch subcommand!").exit();
+        }
+        log::set_max_level(cli_args.logs);
+
+        // Jobs are generated on their own threads which wait for the async compression tasks
+        let report = match tokio::task::block_in_place(|| batch::run(batch_args, &config, &cli_args, is_set)) {
+            Ok(v) => v,
+            Err(e) => Args::command().error(ErrorKind::Io, e.to_string()).exit(),
+        };
+        batch::print_summary(&report);
+        println!("Wrote report to `{}`", batch_args.report);
+
+        if report.failed > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Regenerates a saved image, flags given on the command line override its settings
+    if let Some(filename) = &cli_args.reproduce {
+        let mut saved_config = match reproduce::read_saved_config(filename) {
+            Ok(v) => v,
+            Err(e) => Args::command().error(ErrorKind::Io, e.to_string()).exit(),
+        };
+        overrides::apply_overrides(&mut saved_config, &config, &cli_args, is_set);
+        if !is_set("filename") {
+            saved_config.filename = reproduce::get_default_filename(filename);
+        }
+
+        // Never overwrites the image the config was read from
+        let output = get_save_method(&saved_config.save_method).get_output(&saved_config);
+        if output.is_some_and(|output| Path::new(&output) == Path::new(filename)) {
+            Args::command().error(
+                ErrorKind::ArgumentConflict,
+                format!("Reproducing `{}` would overwrite it, set a different --filename!", filename),
+            ).exit();
+        }
+        config = saved_config;
+    }
 
     if let Some(filename) = config.read_config {
 
@@ -124,11 +291,31 @@ async fn main() {
 
     log::set_max_level(cli_args.logs);
 
-    if cli_args.julia {
-        config.c_init = Some(Complex {
-            real: cli_args.c_real as f32,
-            imaginary: cli_args.c_imaginary as f32,
-        });
+    // Recolors a raw file instead of generating an image
+    if let Some(filename) = &cli_args.recolor {
+        let now = Instant::now();
+        if let Err(e) = raw::recolor(filename, &config) {
+            Args::command().error(ErrorKind::Io, e.to_string()).exit();
+        }
+        log::info!("[Finished in {:.2?}]", now.elapsed());
+        return;
+    }
+
+    // Checks the settings (& loads the orbit trap image) before generating
+    if let Err((kind, e)) = config.validate() {
+        Args::command().error(kind, e).exit();
+    }
+
+    // Keeps the math frame in sync with the deep zoom & view settings
+    config.update_math_frame();
+
+    // Writes the settings instead of generating the image
+    if let Some(filename) = &cli_args.dump_config {
+        if let Err(e) = config_file::write_config_file(filename, &config) {
+            Args::command().error(ErrorKind::Io, e.to_string()).exit();
+        }
+        log::info!("Wrote config to `{}`", filename);
+        return;
     }
 
     let now = Instant::now();
@@ -139,7 +326,7 @@ async fn main() {
     // Runs Config
     let res = match config.gpu {
         true => execution::gpu_eval(&config).await,
-        false => execution::cpu_eval(&config),
+        false => execution::cpu_eval(&config).await,
     };
 
     if let Err(e) = res {
@@ -0,0 +1,201 @@
+#![allow(non_snake_case)]
+
+use crate::structs::{self, Config};
+use crate::cli::Args;
+use crate::math::formula::try_get_formula;
+
+use clap::error::ErrorKind;
+use clap::CommandFactory;
+use num_traits::Float;
+
+/*
+# Purpose
+This section of the code is for defining the different conditions that decide when a pixel
+stops iterating. This gets checked before every iteration of the generation formula.
+*/
+
+pub trait Escape: EscapeFloatMethod + Sync {
+    fn get_alias(&self) -> String;
+    fn get_description(&self) -> String;
+    /// Gets the value `method` compares against from config.
+    fn get_limit(&self, config: &Config) -> f64 { config.bailout }
+    /// Gets if pixels whose orbits are stuck in a cycle can stop iterating.
+    /// Interior checks treat those pixels as inside the set so they are turned off when this is `true`.
+    fn stops_cycles(&self) -> bool { false }
+    /// Returns `true` if the pixel should stop iterating.
+    /// `previous_z` is the value of z before the last iteration (infinitely far away before the first iteration)
+    fn method<T: Float>(&self, z: structs::Complex<T>, previous_z: structs::Complex<T>, limit: T) -> bool where Self: Sized;
+    fn gpu_method(&self) -> String;
+}
+
+/// Object safe versions of `Escape::method` for each float type used in generation.
+/// This is implemented automatically for every `Escape`.
+pub trait EscapeFloatMethod {
+    fn method_f32(&self, z: structs::Complex<f32>, previous_z: structs::Complex<f32>, limit: f32) -> bool;
+    fn method_f64(&self, z: structs::Complex<f64>, previous_z: structs::Complex<f64>, limit: f64) -> bool;
+}
+
+impl<E: Escape> EscapeFloatMethod for E {
+    fn method_f32(&self, z: structs::Complex<f32>, previous_z: structs::Complex<f32>, limit: f32) -> bool {
+        self.method(z, previous_z, limit)
+    }
+    fn method_f64(&self, z: structs::Complex<f64>, previous_z: structs::Complex<f64>, limit: f64) -> bool {
+        self.method(z, previous_z, limit)
+    }
+}
+
+pub struct CIRCLE {}
+impl Escape for CIRCLE {
+    fn get_alias(&self) -> String { "CIRCLE".into() }
+    fn get_description(&self) -> String { "Escapes when |z| > bailout".into() }
+    fn method<T: Float>(&self, z: structs::Complex<T>, _previous_z: structs::Complex<T>, limit: T) -> bool {
+        z.is_greater(limit)
+    }
+    fn gpu_method(&self) -> String {
+        "return length(z.data) > limit;".into()
+    }
+}
+
+pub struct SQUARE {}
+impl Escape for SQUARE {
+    fn get_alias(&self) -> String { "SQUARE".into() }
+    fn get_description(&self) -> String { "Escapes when max(|Re(z)|, |Im(z)|) > bailout".into() }
+    fn method<T: Float>(&self, z: structs::Complex<T>, _previous_z: structs::Complex<T>, limit: T) -> bool {
+        z.real.abs().max(z.imaginary.abs()) > limit
+    }
+    fn gpu_method(&self) -> String {
+        "return max(abs(z.data.x), abs(z.data.y)) > limit;".into()
+    }
+}
+
+pub struct REAL {}
+impl Escape for REAL {
+    fn get_alias(&self) -> String { "REAL".into() }
+    fn get_description(&self) -> String { "Escapes when |Re(z)| > bailout".into() }
+    fn method<T: Float>(&self, z: structs::Complex<T>, _previous_z: structs::Complex<T>, limit: T) -> bool {
+        z.real.abs() > limit
+    }
+    fn gpu_method(&self) -> String {
+        "return abs(z.data.x) > limit;".into()
+    }
+}
+
+pub struct IMAGINARY {}
+impl Escape for IMAGINARY {
+    fn get_alias(&self) -> String { "IMAGINARY".into() }
+    fn get_description(&self) -> String { "Escapes when |Im(z)| > bailout".into() }
+    fn method<T: Float>(&self, z: structs::Complex<T>, _previous_z: structs::Complex<T>, limit: T) -> bool {
+        z.imaginary.abs() > limit
+    }
+    fn gpu_method(&self) -> String {
+        "return abs(z.data.y) > limit;".into()
+    }
+}
+
+pub struct MANHATTAN {}
+impl Escape for MANHATTAN {
+    fn get_alias(&self) -> String { "MANHATTAN".into() }
+    fn get_description(&self) -> String { "Escapes when |Re(z)| + |Im(z)| > bailout".into() }
+    fn method<T: Float>(&self, z: structs::Complex<T>, _previous_z: structs::Complex<T>, limit: T) -> bool {
+        z.real.abs() + z.imaginary.abs() > limit
+    }
+    fn gpu_method(&self) -> String {
+        "return abs(z.data.x) + abs(z.data.y) > limit;".into()
+    }
+}
+
+pub struct CONVERGE {}
+impl Escape for CONVERGE {
+    fn get_alias(&self) -> String { "CONVERGE".into() }
+    fn get_description(&self) -> String { "Stops when z moves less than the tolerance (for Newton type formulas)".into() }
+    fn get_limit(&self, config: &Config) -> f64 { config.tolerance }
+    fn stops_cycles(&self) -> bool { true }
+    fn method<T: Float>(&self, z: structs::Complex<T>, previous_z: structs::Complex<T>, limit: T) -> bool {
+        !(z - previous_z).is_greater(limit)
+    }
+    fn gpu_method(&self) -> String {
+        "return distance(z.data, previous_z.data) <= limit;".into()
+    }
+}
+
+/// Function for getting the escape condition from config.
+/// Uses the default escape condition of the generation formula if it isn't set.
+/// Exits with the list of escape conditions if it can't be found.
+pub fn get_escape(config: &Config) -> &'static dyn Escape {
+    return try_get_escape(config).unwrap_or_else(|e| Args::command().error(ErrorKind::InvalidValue, e).exit());
+}
+
+/// Same as `get_escape` but returns the error message instead of exiting.
+pub fn try_get_escape(config: &Config) -> Result<&'static dyn Escape, String> {
+
+    let escape = match &config.escape_formula {
+        Some(v) => v.to_owned(),
+        None => try_get_formula(config)?.get_escape(),
+    };
+
+    let escapes: Vec<&'static dyn Escape> = vec![
+        &CIRCLE    {},
+        &SQUARE    {},
+        &REAL      {},
+        &IMAGINARY {},
+        &MANHATTAN {},
+        &CONVERGE  {},
+    ];
+
+    // Tries to find function in escapes array
+    for method in escapes.clone() {
+        if method.get_alias() == escape {
+            return Ok(method);
+        }
+    }
+
+    let escape_string: String = escapes
+        .iter()
+        .map(|v| format!("  {}\t{}", v.get_alias(), v.get_description()))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    // If not found return error
+    return Err(format!("Escape condition '{}' not found!\n\nAllowed Escape Conditions:\n{}", escape, escape_string));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::Complex;
+
+    fn complex(real: f64, imaginary: f64) -> Complex<f64> {
+        return Complex { real, imaginary };
+    }
+
+    #[test]
+    fn escape_conditions() {
+        let z = complex(1.5, -1.5);
+        let far = complex(f64::INFINITY, f64::INFINITY);
+
+        // |z| = 2.12, max = 1.5, |Re| = 1.5, |Im| = 1.5, |Re| + |Im| = 3
+        assert!(CIRCLE {}.method(z, far, 2.0));
+        assert!(!SQUARE {}.method(z, far, 2.0));
+        assert!(!REAL {}.method(z, far, 2.0));
+        assert!(IMAGINARY {}.method(z, far, 1.0));
+        assert!(MANHATTAN {}.method(z, far, 2.0));
+
+        // Converging stops once z stops moving (never on the first iteration)
+        assert!(!CONVERGE {}.method(z, far, 1e-6));
+        assert!(!CONVERGE {}.method(z, complex(1.5, -1.4), 1e-6));
+        assert!(CONVERGE {}.method(z, complex(1.5, -1.5 + 1e-9), 1e-6));
+    }
+
+    #[test]
+    fn formula_default_and_limit() {
+        let config = Config { bailout: 4.0, tolerance: 1e-3, ..Config::default() };
+        let escape = get_escape(&config);
+        assert_eq!(escape.get_alias(), "CIRCLE");
+        assert_eq!(escape.get_limit(&config), 4.0);
+
+        let config = Config { escape_formula: Some("CONVERGE".into()), ..config };
+        let escape = get_escape(&config);
+        assert_eq!(escape.get_alias(), "CONVERGE");
+        assert_eq!(escape.get_limit(&config), 1e-3);
+    }
+}
@@ -0,0 +1,571 @@
+/*
+  File for user defined generation formulas.
+
+  Expressions such as `z^3 + c*sin(z)` get parsed into a tree which can either be evaluated on the CPU
+  or turned into GLSL for the `{{ formula }}` slot of the compute shader.
+*/
+
+use crate::math::formula::Formula;
+use crate::structs::Complex;
+
+use num_traits::Float;
+
+use std::f64::consts::{E, PI};
+use std::fmt;
+
+/// The variables which can be used in expressions.
+const VARIABLES: [&str; 3] = ["z", "c", "i"];
+
+/// The constants which can be used in expressions.
+const CONSTANTS: [(&str, f64); 2] = [
+    ("pi", PI),
+    ("e",  E),
+];
+
+/// The functions which can be used in expressions (and the GLSL functions they map to.)
+const FUNCTIONS: [(&str, Function, &str); 13] = [
+    ("sin",  Function::Sin,  "c_sin"),
+    ("cos",  Function::Cos,  "c_cos"),
+    ("tan",  Function::Tan,  "c_tan"),
+    ("sinh", Function::Sinh, "c_sinh"),
+    ("cosh", Function::Cosh, "c_cosh"),
+    ("tanh", Function::Tanh, "c_tanh"),
+    ("exp",  Function::Exp,  "c_exp"),
+    ("log",  Function::Log,  "c_log"),
+    ("sqrt", Function::Sqrt, "c_sqrt"),
+    ("abs",  Function::Abs,  "c_abs"),
+    ("conj", Function::Conj, "conj"),
+    ("re",   Function::Re,   "real_part"),
+    ("im",   Function::Im,   "imaginary_part"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Log,
+    Sqrt,
+    Abs,
+    Conj,
+    Re,
+    Im,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// A single node of a parsed expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Z,
+    C,
+    I,
+    Negate(Box<Node>),
+    Call(Function, Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+}
+
+/// The reasons an expression can fail to parse.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionError {
+    /// The expression isn't valid syntax. `position` is the character the error was found at.
+    Syntax { position: usize, message: String },
+    /// The expression uses names which aren't variables, constants or functions.
+    UnknownIdentifiers(Vec<String>),
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpressionError::Syntax { position, message } => {
+                write!(f, "Syntax error at character {}: {}", position + 1, message)
+            },
+            ExpressionError::UnknownIdentifiers(identifiers) => {
+                write!(
+                    f,
+                    "Unknown identifiers: {}\n  Variables: {}\n  Constants: {}\n  Functions: {}",
+                    identifiers.iter().map(|v| format!("'{}'", v)).collect::<Vec<String>>().join(", "),
+                    VARIABLES.join(", "),
+                    CONSTANTS.iter().map(|v| v.0).collect::<Vec<&str>>().join(", "),
+                    FUNCTIONS.iter().map(|v| v.0).collect::<Vec<&str>>().join(", "),
+                )
+            },
+        }
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+/// Splits the expression into tokens along with the character they start at.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < chars.len() {
+        let start = position;
+        let character = chars[position];
+
+        let token = match character {
+            v if v.is_whitespace() => {
+                position += 1;
+                continue;
+            },
+            '+' => Token::Operator(Operator::Add),
+            '-' => Token::Operator(Operator::Sub),
+            '*' => Token::Operator(Operator::Mul),
+            '/' => Token::Operator(Operator::Div),
+            '^' => Token::Operator(Operator::Pow),
+            '(' => Token::Open,
+            ')' => Token::Close,
+            v if v.is_ascii_digit() || v == '.' => {
+                while position < chars.len() && (chars[position].is_ascii_digit() || chars[position] == '.') {
+                    position += 1;
+                }
+                // Only counts as an exponent if digits follow, otherwise `2e` would be `2` then `e`.
+                if position < chars.len() && (chars[position] == 'e' || chars[position] == 'E') {
+                    let mut exponent_end = position + 1;
+                    if exponent_end < chars.len() && (chars[exponent_end] == '+' || chars[exponent_end] == '-') {
+                        exponent_end += 1;
+                    }
+                    if exponent_end < chars.len() && chars[exponent_end].is_ascii_digit() {
+                        position = exponent_end;
+                        while position < chars.len() && chars[position].is_ascii_digit() {
+                            position += 1;
+                        }
+                    }
+                }
+                let text: String = chars[start..position].iter().collect();
+                match text.parse::<f64>() {
+                    Ok(v) => tokens.push((start, Token::Number(v))),
+                    Err(_) => return Err(ExpressionError::Syntax {
+                        position: start,
+                        message: format!("'{}' isn't a valid number", text),
+                    }),
+                }
+                continue;
+            },
+            v if v.is_alphabetic() || v == '_' => {
+                while position < chars.len() && (chars[position].is_alphanumeric() || chars[position] == '_') {
+                    position += 1;
+                }
+                tokens.push((start, Token::Identifier(chars[start..position].iter().collect())));
+                continue;
+            },
+            v => return Err(ExpressionError::Syntax {
+                position: start,
+                message: format!("Unexpected character '{}'", v),
+            }),
+        };
+
+        tokens.push((start, token));
+        position += 1;
+    }
+
+    return Ok(tokens);
+}
+
+/// Recursive descent parser over the tokens of an expression.
+/// Unknown identifiers are collected instead of stopping the parse so they can all be reported at once.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    end: usize,
+    unknown: Vec<String>,
+}
+
+impl Parser {
+
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.index).map(|v| &v.1);
+    }
+
+    fn position(&self) -> usize {
+        return self.tokens.get(self.index).map(|v| v.0).unwrap_or(self.end);
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ExpressionError> {
+        return Err(ExpressionError::Syntax { position: self.position(), message });
+    }
+
+    /// expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.term()?;
+        while let Some(Token::Operator(operator @ (Operator::Add | Operator::Sub))) = self.peek() {
+            let operator = *operator;
+            self.index += 1;
+            node = Node::Binary(operator, Box::new(node), Box::new(self.term()?));
+        }
+        return Ok(node);
+    }
+
+    /// term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.unary()?;
+        while let Some(Token::Operator(operator @ (Operator::Mul | Operator::Div))) = self.peek() {
+            let operator = *operator;
+            self.index += 1;
+            node = Node::Binary(operator, Box::new(node), Box::new(self.unary()?));
+        }
+        return Ok(node);
+    }
+
+    /// unary := '-' unary | '+' unary | power
+    fn unary(&mut self) -> Result<Node, ExpressionError> {
+        return match self.peek() {
+            Some(Token::Operator(Operator::Sub)) => {
+                self.index += 1;
+                Ok(Node::Negate(Box::new(self.unary()?)))
+            },
+            Some(Token::Operator(Operator::Add)) => {
+                self.index += 1;
+                self.unary()
+            },
+            _ => self.power(),
+        };
+    }
+
+    /// power := atom ('^' unary)?
+    /// The exponent is parsed as a unary so `z^-2` works and `z^2^3` is `z^(2^3)`.
+    fn power(&mut self) -> Result<Node, ExpressionError> {
+        let node = self.atom()?;
+        if let Some(Token::Operator(Operator::Pow)) = self.peek() {
+            self.index += 1;
+            return Ok(Node::Binary(Operator::Pow, Box::new(node), Box::new(self.unary()?)));
+        }
+        return Ok(node);
+    }
+
+    /// atom := number | variable | constant | function '(' expression ')' | '(' expression ')'
+    fn atom(&mut self) -> Result<Node, ExpressionError> {
+
+        let token = match self.peek() {
+            Some(v) => v.clone(),
+            None => return self.error("Unexpected end of expression".into()),
+        };
+        self.index += 1;
+
+        return match token {
+            Token::Number(v) => Ok(Node::Number(v)),
+            Token::Open => {
+                let node = self.expression()?;
+                self.close()?;
+                Ok(node)
+            },
+            Token::Identifier(name) => {
+                if let Some(function) = get_function(&name) {
+                    if self.peek() != Some(&Token::Open) {
+                        return self.error(format!("Expected '(' after function '{}'", name));
+                    }
+                    self.index += 1;
+                    let argument = self.expression()?;
+                    self.close()?;
+                    return Ok(Node::Call(function, Box::new(argument)));
+                }
+
+                if let Some((_, value)) = CONSTANTS.iter().find(|v| v.0 == name) {
+                    return Ok(Node::Number(*value));
+                }
+
+                match name.as_str() {
+                    "z" => Ok(Node::Z),
+                    "c" => Ok(Node::C),
+                    "i" => Ok(Node::I),
+                    _ => {
+                        if !self.unknown.contains(&name) {
+                            self.unknown.push(name);
+                        }
+                        // Skips over the argument of unknown functions so their contents still get checked
+                        if self.peek() == Some(&Token::Open) {
+                            self.index += 1;
+                            self.expression()?;
+                            self.close()?;
+                        }
+                        Ok(Node::Number(0.0))
+                    },
+                }
+            },
+            Token::Close => {
+                self.index -= 1;
+                self.error("Unexpected ')'".into())
+            },
+            Token::Operator(_) => {
+                self.index -= 1;
+                self.error("Expected a value before operator".into())
+            },
+        };
+    }
+
+    fn close(&mut self) -> Result<(), ExpressionError> {
+        if self.peek() != Some(&Token::Close) {
+            return self.error("Expected ')'".into());
+        }
+        self.index += 1;
+        return Ok(());
+    }
+
+}
+
+fn get_function(name: &str) -> Option<Function> {
+    return FUNCTIONS.iter().find(|v| v.0 == name).map(|v| v.1);
+}
+
+/// Gets the value of a node if it is a (possibly negated) number.
+fn get_constant(node: &Node) -> Option<f64> {
+    return match node {
+        Node::Number(v) => Some(*v),
+        Node::Negate(v) => get_constant(v).map(|v| -v),
+        _ => None,
+    };
+}
+
+/// Gets the exponent if it is a whole number small enough for repeated multiplication.
+fn get_integer_exponent(exponent: &Node) -> Option<i32> {
+    return get_constant(exponent)
+        .filter(|v| v.fract() == 0.0 && v.abs() <= i32::MAX as f64)
+        .map(|v| v as i32);
+}
+
+impl Node {
+
+    fn evaluate<T: Float>(&self, c: Complex<T>, z: Complex<T>) -> Complex<T> {
+        return match self {
+            Node::Number(v) => Complex::from_real(T::from(*v).unwrap()),
+            Node::Z => z,
+            Node::C => c,
+            Node::I => Complex { real: T::zero(), imaginary: T::one() },
+            Node::Negate(v) => -v.evaluate(c, z),
+            Node::Call(function, argument) => {
+                let argument = argument.evaluate(c, z);
+                match function {
+                    Function::Sin  => argument.sin(),
+                    Function::Cos  => argument.cos(),
+                    Function::Tan  => argument.tan(),
+                    Function::Sinh => argument.sinh(),
+                    Function::Cosh => argument.cosh(),
+                    Function::Tanh => argument.tanh(),
+                    Function::Exp  => argument.exp(),
+                    Function::Log  => argument.ln(),
+                    Function::Sqrt => argument.sqrt(),
+                    Function::Abs  => Complex::from_real(argument.abs()),
+                    Function::Conj => argument.conj(),
+                    Function::Re   => Complex::from_real(argument.real),
+                    Function::Im   => Complex::from_real(argument.imaginary),
+                }
+            },
+            Node::Binary(Operator::Pow, base, exponent) => {
+                let value = base.evaluate(c, z);
+                match (get_integer_exponent(exponent), get_constant(exponent)) {
+                    (Some(v), _) => value.powi(v),
+                    (None, Some(v)) => value.powf(T::from(v).unwrap()),
+                    (None, None) => value.powc(exponent.evaluate(c, z)),
+                }
+            },
+            Node::Binary(operator, left, right) => {
+                let left = left.evaluate(c, z);
+                let right = right.evaluate(c, z);
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Sub => left - right,
+                    Operator::Mul => left * right,
+                    Operator::Div => left / right,
+                    Operator::Pow => unreachable!(),
+                }
+            },
+        };
+    }
+
+    /// Converts the node into a GLSL expression of type `Complex`.
+    /// The functions used are defined in `comp.glsl`.
+    fn to_glsl(&self) -> String {
+        return match self {
+            Node::Number(v) => format!("number({:?})", v),
+            Node::Z => "z".into(),
+            Node::C => "c".into(),
+            Node::I => "imaginary_unit()".into(),
+            Node::Negate(v) => format!("negate({})", v.to_glsl()),
+            Node::Call(function, argument) => {
+                let name = FUNCTIONS.iter().find(|v| v.1 == *function).unwrap().2;
+                format!("{}({})", name, argument.to_glsl())
+            },
+            Node::Binary(Operator::Pow, base, exponent) => match (get_integer_exponent(exponent), get_constant(exponent)) {
+                (Some(v), _) => format!("power({}, {})", base.to_glsl(), v),
+                (None, Some(v)) => format!("power({}, {:?})", base.to_glsl(), v),
+                (None, None) => format!("power({}, {})", base.to_glsl(), exponent.to_glsl()),
+            },
+            Node::Binary(operator, left, right) => {
+                let name = match operator {
+                    Operator::Add => "add",
+                    Operator::Sub => "sub",
+                    Operator::Mul => "mult",
+                    Operator::Div => "divide",
+                    Operator::Pow => unreachable!(),
+                };
+                format!("{}({}, {})", name, left.to_glsl(), right.to_glsl())
+            },
+        };
+    }
+
+}
+
+/// A generation formula written as an expression of `z` & `c` (such as `z^3 + c*sin(z)`.)
+/// Each iteration sets `z` to the value of the expression.
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+
+    /// Parses an expression, returning an error describing what is wrong if it isn't valid.
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            index: 0,
+            end: source.chars().count(),
+            unknown: Vec::new(),
+        };
+
+        let root = parser.expression()?;
+        if parser.peek().is_some() {
+            return parser.error("Expected an operator".into());
+        }
+        if !parser.unknown.is_empty() {
+            return Err(ExpressionError::UnknownIdentifiers(parser.unknown));
+        }
+
+        return Ok(Self {
+            source: source.to_string(),
+            root,
+        });
+    }
+
+}
+
+impl Formula for Expression {
+    fn get_alias(&self) -> String { self.source.clone() }
+    fn get_description(&self) -> String { "User defined formula".into() }
+    fn method<T: Float>(&self, c: Complex<T>, z: Complex<T>) -> Complex<T> {
+        self.root.evaluate(c, z)
+    }
+    fn gpu_method(&self) -> String {
+        format!("z = {};", self.root.to_glsl())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C: Complex<f64> = Complex { real: -0.4, imaginary: 0.6 };
+    const Z: Complex<f64> = Complex { real: 0.3, imaginary: -0.2 };
+
+    fn evaluate(source: &str) -> Complex<f64> {
+        return Expression::parse(source).unwrap().root.evaluate(C, Z);
+    }
+
+    fn assert_close(a: Complex<f64>, b: Complex<f64>) {
+        assert!((a - b).abs() < 1e-12, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn precedence() {
+        assert_close(evaluate("1 + 2 * 3"), Complex::from_real(7.0));
+        assert_close(evaluate("(1 + 2) * 3"), Complex::from_real(9.0));
+        assert_close(evaluate("2 * 3 ^ 2"), Complex::from_real(18.0));
+        assert_close(evaluate("2 ^ 3 ^ 2"), Complex::from_real(512.0));
+        assert_close(evaluate("8 - 4 - 2"), Complex::from_real(2.0));
+        assert_close(evaluate("8 / 4 / 2"), Complex::from_real(1.0));
+        assert_close(evaluate("z*z + c"), Z * Z + C);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_close(evaluate("-z^2"), -(Z * Z));
+        assert_close(evaluate("(-z)^2"), Z * Z);
+        assert_close(evaluate("--z"), Z);
+        assert_close(evaluate("2^-1"), Complex::from_real(0.5));
+        assert_close(evaluate("c - -z"), C + Z);
+        assert_close(evaluate("+z * -c"), -(Z * C));
+    }
+
+    #[test]
+    fn powers() {
+        let integer = Expression::parse("z^3").unwrap();
+        assert_eq!(integer.gpu_method(), "z = power(z, 3);");
+        assert_close(integer.root.evaluate(C, Z), Z * Z * Z);
+
+        let negative = Expression::parse("z^-2").unwrap();
+        assert_eq!(negative.gpu_method(), "z = power(z, -2);");
+        assert_close(negative.root.evaluate(C, Z), Complex::from_real(1.0) / (Z * Z));
+
+        let fraction = Expression::parse("z^2.5").unwrap();
+        assert_eq!(fraction.gpu_method(), "z = power(z, 2.5);");
+        assert_close(fraction.root.evaluate(C, Z), Z.powf(2.5));
+
+        let complex = Expression::parse("z^c").unwrap();
+        assert_eq!(complex.gpu_method(), "z = power(z, c);");
+        assert_close(complex.root.evaluate(C, Z), Z.powc(C));
+    }
+
+    #[test]
+    fn unknown_identifiers_are_reported_together() {
+        assert_eq!(
+            Expression::parse("foo(z) + bar * baz - foo + sin(qux)").err(),
+            Some(ExpressionError::UnknownIdentifiers(vec!["foo".into(), "bar".into(), "baz".into(), "qux".into()])),
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(matches!(Expression::parse("z +"), Err(ExpressionError::Syntax { position: 3, .. })));
+        assert!(matches!(Expression::parse("sin z"), Err(ExpressionError::Syntax { position: 4, .. })));
+        assert!(matches!(Expression::parse("(z + c"), Err(ExpressionError::Syntax { position: 6, .. })));
+        assert!(matches!(Expression::parse("z c"), Err(ExpressionError::Syntax { position: 2, .. })));
+        assert!(matches!(Expression::parse("z # c"), Err(ExpressionError::Syntax { position: 2, .. })));
+    }
+
+    #[test]
+    fn known_values() {
+        assert_close(evaluate("i * i"), Complex::from_real(-1.0));
+        assert_close(evaluate("exp(i * pi)"), Complex::from_real(-1.0));
+        assert_close(evaluate("log(e)"), Complex::from_real(1.0));
+        assert_close(evaluate("sqrt(z)^2"), Z);
+        assert_close(evaluate("abs(3 + 4*i)"), Complex::from_real(5.0));
+        assert_close(evaluate("conj(z) + re(c) + im(c)"), Complex { real: Z.real + C.real + C.imaginary, imaginary: -Z.imaginary });
+        assert_close(evaluate("sin(z)^2 + cos(z)^2"), Complex::from_real(1.0));
+    }
+
+    #[test]
+    fn glsl_snapshots() {
+        let glsl = |source: &str| Expression::parse(source).unwrap().root.to_glsl();
+        assert_eq!(glsl("z^2 + c"), "add(power(z, 2), c)");
+        assert_eq!(glsl("-z * 2.5 - i"), "sub(mult(negate(z), number(2.5)), imaginary_unit())");
+        assert_eq!(glsl("sin(z) / conj(c)"), "divide(c_sin(z), conj(c))");
+        assert_eq!(glsl("exp(z)^c + re(z) - pi"), "sub(add(power(c_exp(z), c), real_part(z)), number(3.141592653589793))");
+    }
+}
@@ -0,0 +1,186 @@
+/*
+  File for arbitrary precision fixed point numbers.
+  These are used for calculating deep zoom reference orbits.
+*/
+
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+
+use std::ops::{Add, Mul, Sub};
+
+/// Arbitrary precision fixed point number.
+/// The value of the number is `mantissa / 2^bits`.
+/// Numbers should only be compared or combined with numbers that have the same amount of bits.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FixedPoint {
+    mantissa: BigInt,
+    bits: u32,
+}
+
+impl FixedPoint {
+
+    /// Creates a fixed point number with `bits` bits after the binary point from a decimal string
+    /// such as `-1.25`, `.5` or `3.0e-20`.
+    /// Returns `None` if the string isn't a valid decimal number.
+    pub fn parse(value: &str, bits: u32) -> Option<Self> {
+
+        let value = value.trim();
+
+        // Splits off the sign
+        let (negative, value) = match value.strip_prefix('-') {
+            Some(v) => (true, v),
+            None => (false, value.strip_prefix('+').unwrap_or(value)),
+        };
+
+        // Splits off the exponent
+        let (value, exponent) = match value.split_once(['e', 'E']) {
+            Some((v, e)) => (v, e.parse::<i64>().ok()?),
+            None => (value, 0),
+        };
+
+        let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        if !integer.chars().chain(fraction.chars()).all(|v| v.is_ascii_digit()) {
+            return None;
+        }
+
+        // All the digits as one integer, the decimal point is accounted for in the exponent.
+        let digits = format!("{}{}", integer, fraction)
+            .parse::<BigInt>()
+            .unwrap_or_default();
+        let exponent = exponent - fraction.len() as i64;
+
+        let ten = BigInt::from(10);
+        let mut mantissa = match exponent >= 0 {
+            true => (digits * ten.pow(exponent as u32)) << bits,
+            false => (digits << bits) / ten.pow(exponent.unsigned_abs() as u32),
+        };
+
+        if negative {
+            mantissa = -mantissa;
+        }
+
+        return Some(Self {
+            mantissa,
+            bits,
+        });
+    }
+
+    /// Creates a fixed point number equal to zero.
+    pub fn zero(bits: u32) -> Self {
+        return Self {
+            mantissa: BigInt::zero(),
+            bits,
+        };
+    }
+
+    /// Converts the number to the closest `f64` (ignoring rounding in the last bit).
+    pub fn to_f64(&self) -> f64 {
+
+        // Only the top 64 bits of the mantissa matter for an f64
+        let shift = self.mantissa.bits().saturating_sub(64);
+        let top_bits = (&self.mantissa >> shift).to_f64().unwrap_or(0.0);
+
+        // The exponent is split in two so very small numbers don't round to zero too early
+        let exponent = shift as i64 - self.bits as i64;
+        let half = (exponent / 2) as i32;
+        return top_bits * 2f64.powi(half) * 2f64.powi(exponent as i32 - half);
+    }
+
+}
+
+impl Add for &FixedPoint {
+    type Output = FixedPoint;
+
+    fn add(self, other: &FixedPoint) -> FixedPoint {
+        FixedPoint {
+            mantissa : &self.mantissa + &other.mantissa,
+            bits     : self.bits,
+        }
+    }
+}
+
+impl Sub for &FixedPoint {
+    type Output = FixedPoint;
+
+    fn sub(self, other: &FixedPoint) -> FixedPoint {
+        FixedPoint {
+            mantissa : &self.mantissa - &other.mantissa,
+            bits     : self.bits,
+        }
+    }
+}
+
+impl Mul for &FixedPoint {
+    type Output = FixedPoint;
+
+    fn mul(self, other: &FixedPoint) -> FixedPoint {
+        FixedPoint {
+            mantissa : (&self.mantissa * &other.mantissa) >> self.bits,
+            bits     : self.bits,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &str) -> f64 {
+        return FixedPoint::parse(value, 256).unwrap().to_f64();
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= b.abs() * 2.0 * f64::EPSILON, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn negative_values() {
+        assert_eq!(parse("-1.25"), -1.25);
+        assert_eq!(parse("-.5"), -0.5);
+        assert_eq!(parse("+2"), 2.0);
+        assert_eq!(parse(" -3 "), -3.0);
+        assert_eq!(
+            &FixedPoint::parse("-0.75", 64).unwrap() + &FixedPoint::parse("0.75", 64).unwrap(),
+            FixedPoint::zero(64),
+        );
+    }
+
+    #[test]
+    fn exponent_notation() {
+        assert_eq!(parse("1.5E3"), 1500.0);
+        assert_eq!(parse("-2.5e+2"), -250.0);
+        assert_eq!(parse("5e-1"), 0.5);
+        assert_close(parse("3.0e-20"), 3.0e-20);
+        assert_close(parse("-1.7499576837060935e-40"), -1.7499576837060935e-40);
+    }
+
+    #[test]
+    fn many_fractional_digits() {
+        assert_close(parse("0.1234567890123456789012345678901234567890"), 0.12345678901234568);
+
+        // Digits far past what f64 can hold still tell numbers apart
+        let one = FixedPoint::parse("1", 256).unwrap();
+        let close = FixedPoint::parse("1.000000000000000000000000000000000000001", 256).unwrap();
+        assert!(close > one);
+        assert_close((&close - &one).to_f64(), 1e-39);
+    }
+
+    #[test]
+    fn invalid_input() {
+        for value in ["", "-", "+", ".", "-.", "abc", "1.2.3", "1e", "1e5.5", "--1", "1 2", "0x10", "1,5", "e5"] {
+            assert_eq!(FixedPoint::parse(value, 64), None, "'{}' shouldn't parse", value);
+        }
+    }
+
+    #[test]
+    fn to_f64_round_trip() {
+        assert_eq!(FixedPoint::zero(64).to_f64(), 0.0);
+        for value in [1.0, -1.0, 0.1, std::f64::consts::PI, -1.7499576837060935, 1e-30, 123456.789, -6.02214076e23] {
+            assert_close(parse(&format!("{:e}", value)), value);
+            assert_close(parse(&format!("{}", value)), value);
+        }
+    }
+}
@@ -1,10 +1,13 @@
 #![allow(non_snake_case)]
 
-use crate::structs;
+use crate::structs::{self, Config};
 use crate::cli::Args;
+use crate::math::expression::Expression;
+use crate::math::polynomial::Polynomial;
 
 use clap::error::ErrorKind;
 use clap::CommandFactory;
+use num_traits::Float;
 
 /*
 # Purpose
@@ -12,19 +15,73 @@ This section of the code is for defining the different functions that are used
 to generate images. This is the function that gets run on each pixel.
 */
 
-pub trait Formula {
+pub trait Formula: FloatMethod + Sync {
     fn get_alias(&self) -> String;
     fn get_description(&self) -> String;
-    fn method(&self, c: structs::Complex, z: structs::Complex) -> structs::Complex;
+    /// Gets the alias of the escape condition used when one isn't set in config.
+    fn get_escape(&self) -> String { "CIRCLE".into() }
+    /// Gets the value z starts at when not using julia settings (the pixel position is used if this is `None`.)
+    fn get_start(&self) -> Option<structs::Complex<f64>> { None }
+    /// Gets the roots pixels can converge to. Pixels which converge get colored by the root they end up closest to.
+    /// Returns an error message if the roots can't be found.
+    fn get_roots(&self) -> Result<Vec<structs::Complex<f64>>, String> { Ok(Vec::new()) }
+    /// Gets if the formula is z^2 + c, which lets generation skip pixels in the main cardioid & period 2 bulb
+    /// & pixels whose orbits fall into a cycle (since they never escape.)
+    fn supports_interior_checks(&self) -> bool { false }
+    fn method<T: Float>(&self, c: structs::Complex<T>, z: structs::Complex<T>) -> structs::Complex<T> where Self: Sized;
     fn gpu_method(&self) -> String;
+    /// Gets the derivative of the next z with respect to the pixel position.
+    /// `dz` is the derivative of z & `dc` is the derivative of c (1 for mandelbrot settings, 0 for julia settings.)
+    /// By default this is estimated numerically from `method`.
+    fn derivative<T: Float>(&self, c: structs::Complex<T>, z: structs::Complex<T>, dz: structs::Complex<T>, dc: T) -> structs::Complex<T> where Self: Sized {
+        // The step shrinks as dz grows so the shifted z stays close to z
+        let h = T::epsilon().sqrt() / dz.abs().max(T::one());
+        let shifted = self.method(
+            c + structs::Complex::from_real(h * dc),
+            z + dz * structs::Complex::from_real(h),
+        );
+        (shifted - self.method(c, z)) * structs::Complex::from_real(T::one() / h)
+    }
+    /// GLSL version of `derivative`, the body of a function which returns the new `dz`.
+    fn gpu_derivative(&self) -> String {
+        "
+        Complex h = number(derivative_step / max(1.0, length(dz.data)));
+        Complex shifted = formula(add(z, mult(dz, h)), add(c, mult(h, number(dc))));
+        return divide(sub(shifted, formula(z, c)), h);
+        ".trim().into()
+    }
+}
+
+/// Object safe versions of `Formula::method` & `Formula::derivative` for each float type used in generation.
+/// This is implemented automatically for every `Formula`.
+pub trait FloatMethod {
+    fn method_f32(&self, c: structs::Complex<f32>, z: structs::Complex<f32>) -> structs::Complex<f32>;
+    fn method_f64(&self, c: structs::Complex<f64>, z: structs::Complex<f64>) -> structs::Complex<f64>;
+    fn derivative_f32(&self, c: structs::Complex<f32>, z: structs::Complex<f32>, dz: structs::Complex<f32>, dc: f32) -> structs::Complex<f32>;
+    fn derivative_f64(&self, c: structs::Complex<f64>, z: structs::Complex<f64>, dz: structs::Complex<f64>, dc: f64) -> structs::Complex<f64>;
+}
+
+impl<F: Formula> FloatMethod for F {
+    fn method_f32(&self, c: structs::Complex<f32>, z: structs::Complex<f32>) -> structs::Complex<f32> {
+        self.method(c, z)
+    }
+    fn method_f64(&self, c: structs::Complex<f64>, z: structs::Complex<f64>) -> structs::Complex<f64> {
+        self.method(c, z)
+    }
+    fn derivative_f32(&self, c: structs::Complex<f32>, z: structs::Complex<f32>, dz: structs::Complex<f32>, dc: f32) -> structs::Complex<f32> {
+        self.derivative(c, z, dz, dc)
+    }
+    fn derivative_f64(&self, c: structs::Complex<f64>, z: structs::Complex<f64>, dz: structs::Complex<f64>, dc: f64) -> structs::Complex<f64> {
+        self.derivative(c, z, dz, dc)
+    }
 }
 
 struct DBG {}
 impl Formula for DBG {
     fn get_alias(&self) -> String { "DBG".into() }
     fn get_description(&self) -> String { "A Debug Formula for development Purposes.".into() }
-    fn method(&self, _c: structs::Complex, mut z: structs::Complex) -> structs::Complex {
-        z.real += 0.1;
+    fn method<T: Float>(&self, _c: structs::Complex<T>, mut z: structs::Complex<T>) -> structs::Complex<T> {
+        z.real = z.real + T::from(0.1).unwrap();
         z
 
     }
@@ -40,7 +97,8 @@ pub struct SD {}
 impl Formula for SD {
     fn get_alias(&self) -> String { "SD".into() }
     fn get_description(&self) -> String { "Standard z = z^2 + c".into() }
-    fn method(&self, c: structs::Complex, z: structs::Complex) -> structs::Complex {
+    fn supports_interior_checks(&self) -> bool { true }
+    fn method<T: Float>(&self, c: structs::Complex<T>, z: structs::Complex<T>) -> structs::Complex<T> {
         z * z + c
     }
     fn gpu_method(&self) -> String {
@@ -48,16 +106,23 @@ impl Formula for SD {
         z = add(mult(z, z), c);
         ".trim().into()
     }
+    fn derivative<T: Float>(&self, _c: structs::Complex<T>, z: structs::Complex<T>, dz: structs::Complex<T>, dc: T) -> structs::Complex<T> {
+        let two = structs::Complex::from_real(T::one() + T::one());
+        two * z * dz + structs::Complex::from_real(dc)
+    }
+    fn gpu_derivative(&self) -> String {
+        "return add(mult(number(2.0), mult(z, dz)), number(dc));".into()
+    }
 }
 
 pub struct R {}
 impl Formula for R {
     fn get_alias(&self) -> String { "R".into() }
     fn get_description(&self) -> String { "Custom Rabbit Generator".into() }
-    fn method(&self, c: structs::Complex, z: structs::Complex) -> structs::Complex {
+    fn method<T: Float>(&self, c: structs::Complex<T>, z: structs::Complex<T>) -> structs::Complex<T> {
         let mut new_z = z * z + c;
-        new_z.imaginary -= z.real;
-        new_z.real -= z.imaginary;
+        new_z.imaginary = new_z.imaginary - z.real;
+        new_z.real = new_z.real - z.imaginary;
         return new_z;
     }
     fn gpu_method(&self) -> String {
@@ -74,13 +139,13 @@ pub struct ABR {}
 impl Formula for ABR {
     fn get_alias(&self) -> String { "ABR".into() }
     fn get_description(&self) -> String { "Absolute Value Rabbit Generator".into() }
-    fn method(&self, c: structs::Complex, z: structs::Complex) -> structs::Complex {
+    fn method<T: Float>(&self, c: structs::Complex<T>, z: structs::Complex<T>) -> structs::Complex<T> {
         let mut new_z = z * z;
-        if new_z.imaginary < 0.0 {
-            new_z.imaginary *= -1.0;
+        if new_z.imaginary < T::zero() {
+            new_z.imaginary = -new_z.imaginary;
         }
-        new_z.imaginary -= z.real;
-        new_z.real -= z.imaginary;
+        new_z.imaginary = new_z.imaginary - z.real;
+        new_z.real = new_z.real - z.imaginary;
         return new_z + c;
     }
     fn gpu_method(&self) -> String {
@@ -101,10 +166,10 @@ pub struct BS {}
 impl Formula for BS {
     fn get_alias(&self) -> String { "BS".into() }
     fn get_description(&self) -> String { "Burning Ship Generator".into() }
-    fn method(&self, c: structs::Complex, mut z: structs::Complex) -> structs::Complex {
+    fn method<T: Float>(&self, c: structs::Complex<T>, mut z: structs::Complex<T>) -> structs::Complex<T> {
         z = z * z;
-        if z.imaginary > 0.0 {
-            z.imaginary = z.imaginary * -1.0;
+        if z.imaginary > T::zero() {
+            z.imaginary = -z.imaginary;
         }
         return z + c;
     }
@@ -123,7 +188,7 @@ pub struct SYM {}
 impl Formula for SYM {
     fn get_alias(&self) -> String { "SYM".into() }
     fn get_description(&self) -> String { "A Symetrical Mandelbrot Like Generation".into() }
-    fn method(&self, c: structs::Complex, z: structs::Complex) -> structs::Complex {
+    fn method<T: Float>(&self, c: structs::Complex<T>, z: structs::Complex<T>) -> structs::Complex<T> {
         z * z + c - z
     }
     fn gpu_method(&self) -> String {
@@ -138,34 +203,131 @@ impl Formula for SYM {
     }
 }
 
+pub struct MULTI {
+    power: f64,
+}
+impl Formula for MULTI {
+    fn get_alias(&self) -> String { "MULTI".into() }
+    fn get_description(&self) -> String { "Multibrot z = z^p + c (p is set with --power)".into() }
+    fn supports_interior_checks(&self) -> bool { self.get_integer_power() == Some(2) }
+    fn method<T: Float>(&self, c: structs::Complex<T>, z: structs::Complex<T>) -> structs::Complex<T> {
+        match self.get_integer_power() {
+            Some(power) => z.powi(power) + c,
+            None => z.powf(T::from(self.power).unwrap()) + c,
+        }
+    }
+    fn gpu_method(&self) -> String {
+        match self.get_integer_power() {
+            Some(power) => format!("z = add(power(z, {}), c);", power),
+            None => format!("z = add(power(z, {:?}), c);", self.power),
+        }
+    }
+    fn derivative<T: Float>(&self, _c: structs::Complex<T>, z: structs::Complex<T>, dz: structs::Complex<T>, dc: T) -> structs::Complex<T> {
+        let power = structs::Complex::from_real(T::from(self.power).unwrap());
+        let z_power = match self.get_integer_power() {
+            Some(power) => z.powi(power - 1),
+            None => z.powf(T::from(self.power - 1.0).unwrap()),
+        };
+        power * z_power * dz + structs::Complex::from_real(dc)
+    }
+    fn gpu_derivative(&self) -> String {
+        let z_power = match self.get_integer_power() {
+            Some(power) => format!("power(z, {})", power - 1),
+            None => format!("power(z, {:?})", self.power - 1.0),
+        };
+        format!("return add(mult(number({:?}), mult({}, dz)), number(dc));", self.power, z_power)
+    }
+}
+
+impl MULTI {
+    /// Whole number powers use repeated multiplication which is faster & more accurate.
+    fn get_integer_power(&self) -> Option<i32> {
+        if self.power.fract() == 0.0 && self.power.abs() <= i32::MAX as f64 {
+            return Some(self.power as i32);
+        }
+        return None;
+    }
+}
+
+pub struct NEWTON {
+    polynomial: Polynomial,
+}
+impl Formula for NEWTON {
+    fn get_alias(&self) -> String { "NEWTON".into() }
+    fn get_description(&self) -> String { "Newton's method z = z - p(z) / p'(z) (p is set with --polynomial)".into() }
+    fn get_escape(&self) -> String { "CONVERGE".into() }
+    fn get_roots(&self) -> Result<Vec<structs::Complex<f64>>, String> { self.polynomial.roots() }
+    fn method<T: Float>(&self, _c: structs::Complex<T>, z: structs::Complex<T>) -> structs::Complex<T> {
+        let (p, dp) = self.polynomial.evaluate(z);
+        z - p / dp
+    }
+    fn gpu_method(&self) -> String {
+        format!("{}\n        z = sub(z, divide(p, dp));", self.polynomial.gpu_method())
+    }
+}
+
+pub struct NOVA {
+    polynomial: Polynomial,
+}
+impl Formula for NOVA {
+    fn get_alias(&self) -> String { "NOVA".into() }
+    fn get_description(&self) -> String { "Nova z = z - p(z) / p'(z) + c starting at z = 1 (p is set with --polynomial)".into() }
+    fn get_escape(&self) -> String { "CONVERGE".into() }
+    fn get_start(&self) -> Option<structs::Complex<f64>> { Some(structs::Complex::from_real(1.0)) }
+    fn method<T: Float>(&self, c: structs::Complex<T>, z: structs::Complex<T>) -> structs::Complex<T> {
+        let (p, dp) = self.polynomial.evaluate(z);
+        z - p / dp + c
+    }
+    fn gpu_method(&self) -> String {
+        format!("{}\n        z = add(sub(z, divide(p, dp)), c);", self.polynomial.gpu_method())
+    }
+}
+
 /// Function for getting generator formula from FORMULAS const
-pub fn get_formula(formula: &str) -> &dyn Formula {
-
-    let formulas: Vec<&dyn Formula> = vec![
-        &SD  {},
-        &R   {},
-        &ABR {},
-        &BS  {},
-        &SYM {},
-        &DBG {},
+/// If the formula isn't one of the built in formulas it gets parsed as an expression (such as `z^3 + c*sin(z)`.)
+/// Exits with the list of formulas if it can't be found.
+pub fn get_formula(config: &Config) -> Box<dyn Formula> {
+    return try_get_formula(config).unwrap_or_else(|e| Args::command().error(ErrorKind::InvalidValue, e).exit());
+}
+
+/// Same as `get_formula` but returns the error message instead of exiting.
+pub fn try_get_formula(config: &Config) -> Result<Box<dyn Formula>, String> {
+
+    let formula = config.gen_formula.as_str();
+    let mut formulas: Vec<Box<dyn Formula>> = vec![
+        Box::new(SD  {}),
+        Box::new(MULTI { power: config.power }),
+        Box::new(NEWTON { polynomial: Polynomial::new(&config.polynomial) }),
+        Box::new(NOVA { polynomial: Polynomial::new(&config.polynomial) }),
+        Box::new(R   {}),
+        Box::new(ABR {}),
+        Box::new(BS  {}),
+        Box::new(SYM {}),
+        Box::new(DBG {}),
     ];
 
     // Tries to find function in FORMULAS const
-    for method in formulas.clone() {
-        if method.get_alias() == formula.to_string() {
-            return method;
-        }
+    if let Some(index) = formulas.iter().position(|v| v.get_alias() == formula) {
+        return Ok(formulas.swap_remove(index));
     }
 
+    // Tries to parse the formula as an expression
+    let expression_error = match Expression::parse(formula) {
+        Ok(v) => return Ok(Box::new(v)),
+        Err(e) => e,
+    };
+
     let formula_string: String = formulas
         .iter()
         .map(|v| format!("  {}\t{}", v.get_alias(), v.get_description()))
         .collect::<Vec<String>>()
         .join("\n");
 
-    // If not found throw error
-    Args::command().error(
-        ErrorKind::InvalidValue,
-        format!("Function generation method '{}' not found!\n\nAllowed Formulas:\n{}", formula, formula_string)
-    ).exit();
+    // If not found return error
+    return Err(format!(
+        "Function generation method '{}' not found!\n\nAllowed Formulas:\n{}\n\nFormulas can also be expressions of z & c (such as 'z^3 + c*sin(z)') however this couldn't be parsed as one:\n{}",
+        formula,
+        formula_string,
+        expression_error,
+    ));
 }
@@ -0,0 +1,167 @@
+/*
+  File for the checks which find pixels inside the set without iterating up to `max_i`.
+  These only apply to formulas which support them (see `Formula::supports_interior_checks`.)
+*/
+
+use num_traits::Float;
+
+use crate::math::escape::Escape;
+use crate::math::formula::Formula;
+use crate::structs::{Complex, Config};
+
+/// Checks if the interior checks can be used with the config.
+/// Travel distance colors the inside of the set by its orbits so every iteration is needed, and
+/// escape conditions which stop cycles would treat the skipped pixels as escaped.
+pub fn use_interior_checks(config: &Config, formula: &dyn Formula, escape: &dyn Escape) -> bool {
+    return formula.supports_interior_checks() && !escape.stops_cycles() && !config.travel_distance;
+}
+
+/// Checks if `c` is in the main cardioid or the period 2 bulb of the Mandelbrot set.
+/// The GLSL `in_main_bulbs` function does the same.
+pub fn in_main_bulbs<T: Float>(c: Complex<T>) -> bool {
+    let quarter = T::from(0.25).unwrap();
+    let x = c.real - quarter;
+    let y_squared = c.imaginary * c.imaginary;
+
+    let q = x * x + y_squared;
+    let in_cardioid = q * (q + x) <= quarter * y_squared;
+
+    let x = c.real + T::one();
+    let in_bulb = x * x + y_squared <= T::from(0.0625).unwrap();
+
+    return in_cardioid || in_bulb;
+}
+
+/// The part of a pixel's size z has to come back within to count as being stuck in a cycle.
+const PERIODICITY_PIXEL_FRACTION: f64 = 1e-3;
+
+/// Gets how close z has to come back to a saved value to count as being stuck in a cycle.
+/// This follows the size of a pixel so orbits which only get caught wrongly are ones that pass
+/// really close to a cycle, which only happens right next to the edge of the set. It is never less
+/// than a few steps of float precision so orbits which have settled into a cycle always get caught.
+/// Returns `None` if pixels are smaller than the float type can tell apart (the check is skipped then.)
+pub fn get_periodicity_tolerance<T: Float>(config: &Config) -> Option<T> {
+    let pixel_size = T::from(config.math_frame.get_pixel_size()).unwrap();
+    if pixel_size < T::epsilon() {
+        return None;
+    }
+    let tolerance = pixel_size * T::from(PERIODICITY_PIXEL_FRACTION).unwrap();
+    return Some(tolerance.max(T::epsilon() * T::from(4.0).unwrap()));
+}
+
+/// Brent style cycle detection for orbits.
+/// z is compared against a saved value which gets replaced after 1, 2, 4, 8... iterations
+/// so cycles of any length get caught without storing the orbit.
+pub struct Periodicity<T: Float> {
+    saved_z: Complex<T>,
+    steps: u64,
+    period_length: u64,
+    tolerance: T,
+}
+
+impl<T: Float> Periodicity<T> {
+
+    /// Starts checking the orbit starting at `z` (see `get_periodicity_tolerance` for `tolerance`.)
+    pub fn new(z: Complex<T>, tolerance: T) -> Self {
+        return Self {
+            saved_z: z,
+            steps: 0,
+            period_length: 1,
+            tolerance: tolerance * tolerance,
+        };
+    }
+
+    /// Checks the next value of the orbit.
+    /// Returns `true` once the orbit is stuck in a cycle (which means it never escapes.)
+    pub fn is_periodic(&mut self, z: Complex<T>) -> bool {
+        if (z - self.saved_z).norm_sqr() < self.tolerance {
+            return true;
+        }
+
+        self.steps += 1;
+        if self.steps == self.period_length {
+            self.saved_z = z;
+            self.steps = 0;
+            self.period_length *= 2;
+        }
+        return false;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::escape::get_escape;
+    use crate::math::formula::get_formula;
+    use crate::structs::MathFrame;
+
+    fn get_config(pixel_size: f64) -> Config {
+        return Config {
+            math_frame: MathFrame { factor_x: pixel_size, factor_y: pixel_size, shear_x: 0.0, shear_y: 0.0, offset_x: -2.0, offset_y: -2.0 },
+            ..Config::default()
+        };
+    }
+
+    #[test]
+    fn main_bulbs() {
+        assert!(in_main_bulbs(Complex { real: 0.0, imaginary: 0.0 }));
+        assert!(in_main_bulbs(Complex { real: 0.2, imaginary: 0.5 }));
+        assert!(in_main_bulbs(Complex { real: -1.0, imaginary: 0.2 }));
+        // Inside the set, but in a smaller bulb
+        assert!(!in_main_bulbs(Complex { real: -0.12, imaginary: 0.75 }));
+        assert!(!in_main_bulbs(Complex { real: 0.3, imaginary: 0.0 }));
+        assert!(!in_main_bulbs(Complex { real: -1.3, imaginary: 0.0 }));
+    }
+
+    #[test]
+    fn tolerance_follows_pixel_size() {
+        let zoomed_out = get_periodicity_tolerance::<f64>(&get_config(1e-2)).unwrap();
+        let zoomed_in = get_periodicity_tolerance::<f64>(&get_config(1e-8)).unwrap();
+        assert!((zoomed_out - 1e-5).abs() < 1e-18);
+        assert!((zoomed_in - 1e-11).abs() < 1e-24);
+
+        // Never tighter than float precision & skipped once pixels can't be told apart
+        assert_eq!(get_periodicity_tolerance::<f32>(&get_config(1e-6)), Some(f32::EPSILON * 4.0));
+        assert_eq!(get_periodicity_tolerance::<f32>(&get_config(1e-8)), None);
+        assert!(get_periodicity_tolerance::<f64>(&get_config(1e-8)).is_some());
+    }
+
+    #[test]
+    fn cycles_are_detected() {
+        let step = |z: Complex<f64>, c: Complex<f64>| z * z + c;
+
+        // c = -1 falls into the cycle 0, -1, 0, -1...
+        let c = Complex::from_real(-1.0);
+        let mut z = c;
+        let mut periodicity = Periodicity::new(z, 1e-9);
+        assert!((0..8).any(|_| {
+            z = step(z, c);
+            periodicity.is_periodic(z)
+        }));
+
+        // c = 0.3 escapes, slowly passing close to z = 0.5
+        let c = Complex::from_real(0.3);
+        let mut z = c;
+        let mut periodicity = Periodicity::new(z, 1e-9);
+        for _ in 0..100 {
+            z = step(z, c);
+            if z.is_greater(2.0) {
+                break;
+            }
+            assert!(!periodicity.is_periodic(z));
+        }
+    }
+
+    #[test]
+    fn checks_only_apply_to_standard_formula() {
+        let config = Config { travel_distance: false, ..Config::default() };
+        assert!(use_interior_checks(&config, get_formula(&config).as_ref(), get_escape(&config)));
+
+        let travel = Config { travel_distance: true, ..config.clone() };
+        assert!(!use_interior_checks(&travel, get_formula(&travel).as_ref(), get_escape(&travel)));
+
+        let burning_ship = Config { gen_formula: "BS".into(), ..config };
+        assert!(!use_interior_checks(&burning_ship, get_formula(&burning_ship).as_ref(), get_escape(&burning_ship)));
+    }
+}
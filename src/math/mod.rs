@@ -1,2 +1,9 @@
 pub mod formula;
-
+pub mod escape;
+pub mod expression;
+pub mod fixed_point;
+pub mod interior;
+pub mod orbit_trap;
+pub mod perturbation;
+pub mod polynomial;
+pub mod random;
@@ -0,0 +1,191 @@
+/*
+  File for orbit traps.
+  An orbit trap is a shape in math space, pixels get colored by the closest their orbit gets to it
+  instead of by their iteration count.
+*/
+
+use crate::structs::{Complex, OrbitTrap, TrapShape};
+
+/// An image placed over the trap, stored as how far each pixel is from trapping the orbit.
+pub struct TrapImage {
+    pub width: u32,
+    pub height: u32,
+    /// The darkness of each pixel (from 0 to 1), transparent pixels count as dark
+    pub values: Vec<f32>,
+}
+
+impl TrapImage {
+
+    /// Loads the image at `path`.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("Can't open trap image `{}`: {}", path, e))?
+            .to_luma_alpha8();
+        return Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            values: image
+                .pixels()
+                .map(|pixel| 1.0 - pixel[0] as f32 / 255.0 * pixel[1] as f32 / 255.0)
+                .collect(),
+        });
+    }
+
+}
+
+/// An orbit trap ready to be compared against orbits.
+pub struct Trap {
+    shape: TrapShape,
+    center: Complex<f64>,
+    /// The direction of the line, cross & image (from the angle of the trap)
+    direction: Complex<f64>,
+    radius: f64,
+    image: Option<TrapImage>,
+}
+
+impl Trap {
+
+    /// Creates the trap described by `settings`, loading its image if it is an image trap.
+    pub fn new(settings: &OrbitTrap) -> Result<Self, String> {
+        let image = match (settings.shape, &settings.image) {
+            (TrapShape::Image, Some(path)) => Some(TrapImage::open(path)?),
+            (TrapShape::Image, None) => return Err("Image traps need an image (set with --trap-image)!".into()),
+            _ => None,
+        };
+        if settings.radius <= 0.0 {
+            return Err(format!("The radius of the orbit trap has to be larger than 0 (got {})!", settings.radius));
+        }
+
+        let angle = settings.angle.to_radians();
+        return Ok(Self {
+            shape: settings.shape,
+            center: settings.center,
+            direction: Complex { real: angle.cos(), imaginary: angle.sin() },
+            radius: settings.radius,
+            image,
+        });
+    }
+
+    /// Gets the distance from `z` to the trap in units of its radius.
+    /// For image traps this is half the darkness of the image under `z` so the brightest & darkest parts of
+    /// the image are half a color cycle apart (instead of a whole cycle which would give them the same color.)
+    /// The GLSL `trap_distance` function does the same.
+    pub fn distance(&self, z: Complex<f64>) -> f64 {
+        let p = z - self.center;
+
+        // The position along & across the direction of the trap
+        let along = p.real * self.direction.real + p.imaginary * self.direction.imaginary;
+        let across = p.imaginary * self.direction.real - p.real * self.direction.imaginary;
+
+        return match self.shape {
+            TrapShape::Point => p.abs() / self.radius,
+            TrapShape::Line => across.abs() / self.radius,
+            TrapShape::Cross => across.abs().min(along.abs()) / self.radius,
+            TrapShape::Circle => (p.abs() - self.radius).abs() / self.radius,
+            TrapShape::Image => 0.5 * self.image_distance(along, across),
+        };
+    }
+
+    /// Gets the darkness of the image at (`along`, `across`) relative to the center of the trap.
+    /// The image is `2 * radius` wide & keeps its aspect ratio.
+    fn image_distance(&self, along: f64, across: f64) -> f64 {
+        let Some(image) = &self.image else { return 1.0 };
+        let (width, height) = (image.width as f64, image.height as f64);
+
+        let x = (along / self.radius + 1.0) * 0.5 * width;
+        let y = height * 0.5 - across / self.radius * 0.5 * width;
+        if x < 0.0 || y < 0.0 || x >= width || y >= height {
+            return 1.0;
+        }
+        return image.values[y as usize * image.width as usize + x as usize] as f64;
+    }
+
+    /// Gets the body of the GLSL `trap_distance` function.
+    /// `p` is the position relative to the center of the trap & `along` & `across` are the same as in `distance`.
+    pub fn gpu_method(&self) -> String {
+        return match self.shape {
+            TrapShape::Point => "return length(p) / trap_radius;".into(),
+            TrapShape::Line => "return abs(across) / trap_radius;".into(),
+            TrapShape::Cross => "return min(abs(across), abs(along)) / trap_radius;".into(),
+            TrapShape::Circle => "return abs(length(p) - trap_radius) / trap_radius;".into(),
+            TrapShape::Image => {
+                let (width, height) = self.image.as_ref().map_or((1, 1), |v| (v.width, v.height));
+                format!("
+                    float x = (along / trap_radius + 1.0) * 0.5 * {width}.0;
+                    float y = {height}.0 * 0.5 - across / trap_radius * 0.5 * {width}.0;
+                    if (x < 0.0 || y < 0.0 || x >= {width}.0 || y >= {height}.0) {{
+                        return 0.5;
+                    }}
+                    return 0.5 * trap_image[int(y) * {width} + int(x)];
+                ")
+            },
+        };
+    }
+
+    /// Gets the values of the image for the GPU (a single unused value if this isn't an image trap.)
+    pub fn get_image_values(&self) -> Vec<f32> {
+        return match &self.image {
+            Some(image) => image.values.clone(),
+            None => vec![1.0],
+        };
+    }
+
+    /// Gets the direction of the trap (from its angle.)
+    pub fn get_direction(&self) -> Complex<f64> {
+        return self.direction;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trap(shape: TrapShape, angle: f64) -> Trap {
+        return Trap::new(&OrbitTrap {
+            shape,
+            center: Complex { real: 1.0, imaginary: 0.0 },
+            angle,
+            radius: 0.5,
+            image: None,
+        }).unwrap();
+    }
+
+    #[test]
+    fn shape_distances() {
+        let z = Complex { real: 1.0, imaginary: 1.0 };
+        assert!((trap(TrapShape::Point, 0.0).distance(z) - 2.0).abs() < 1e-12);
+        assert!((trap(TrapShape::Line, 0.0).distance(z) - 2.0).abs() < 1e-12);
+        // A vertical line goes through `z`
+        assert!(trap(TrapShape::Line, 90.0).distance(z).abs() < 1e-12);
+        assert!(trap(TrapShape::Cross, 0.0).distance(z).abs() < 1e-12);
+        assert!((trap(TrapShape::Circle, 0.0).distance(z) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn invalid_traps() {
+        let settings = OrbitTrap {
+            shape: TrapShape::Image,
+            center: Complex { real: 0.0, imaginary: 0.0 },
+            angle: 0.0,
+            radius: 1.0,
+            image: None,
+        };
+        assert!(Trap::new(&settings).is_err());
+        assert!(Trap::new(&OrbitTrap { shape: TrapShape::Point, radius: 0.0, ..settings }).is_err());
+    }
+
+    #[test]
+    fn image_distances() {
+        let trap = Trap {
+            shape: TrapShape::Image,
+            image: Some(TrapImage { width: 2, height: 2, values: vec![0.0, 1.0, 1.0, 0.0] }),
+            ..trap(TrapShape::Point, 0.0)
+        };
+        // The top left pixel is bright & the top right pixel is dark
+        assert_eq!(trap.distance(Complex { real: 0.75, imaginary: 0.25 }), 0.0);
+        assert_eq!(trap.distance(Complex { real: 1.25, imaginary: 0.25 }), 0.5);
+        // Outside of the image
+        assert_eq!(trap.distance(Complex { real: 3.0, imaginary: 0.0 }), 0.5);
+    }
+}
@@ -0,0 +1,223 @@
+/*
+  File for perturbation theory based deep zoom generation.
+
+  A single reference orbit is calculated at the center of the image with arbitrary precision.
+  Every pixel is then iterated as a small f64 offset (delta) from that reference orbit:
+    delta_{n+1} = 2 * Z_n * delta_n + delta_n^2 + delta_c
+  Whenever the pixel's orbit gets closer to zero than the reference orbit (which is what causes
+  glitches) or the reference orbit runs out, the pixel is rebased onto the start of the reference.
+*/
+
+use crate::math::escape::{get_escape, Escape};
+use crate::math::fixed_point::FixedPoint;
+use crate::math::orbit_trap::Trap;
+use crate::structs::{Complex, Config, MathFrame, Sample};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The amount of extra bits of precision used on top of what is needed to tell pixels apart.
+const EXTRA_BITS: u32 = 64;
+
+/// A high precision orbit at the center of the image which pixels get iterated against.
+pub struct ReferenceOrbit {
+    /// The values of the orbit starting from `Z_0 = 0`, converted to f64.
+    orbit: Vec<Complex<f64>>,
+    /// The math frame of the image, only the factors are used since the offset is too imprecise at deep zooms.
+    math_frame: MathFrame,
+    /// The pixel position of the center of the image.
+    center_x: f64,
+    center_y: f64,
+    /// The amount of pixels which had to be rebased to avoid glitches.
+    rebased_pixels: AtomicU64,
+    /// The condition for when pixels stop iterating.
+    escape_function: &'static dyn Escape,
+    /// The trap pixels get colored by (for orbit trap coloring)
+    orbit_trap: Option<Trap>,
+}
+
+impl ReferenceOrbit {
+
+    /// Function for calculating the reference orbit from config.
+    /// Returns an error message if the config can't be used for deep zooms.
+    pub fn new(config: &Config) -> Result<Self, String> {
+
+        let deep_zoom = config.deep_zoom
+            .as_ref()
+            .ok_or("Deep zoom settings are required to calculate a reference orbit!")?;
+
+        if config.gen_formula != "SD" || config.c_init.is_some() {
+            return Err(format!("Deep zooms are only supported for the 'SD' formula without julia settings! (Got formula '{}')", config.gen_formula));
+        }
+
+        let pixel_size = 2.0 * deep_zoom.radius / (config.size_y.max(2) - 1) as f64;
+
+        // Enough bits to tell neighbouring pixels apart, plus some extra for the orbit's error
+        // to stay well below the size of a pixel.
+        let bits = (-pixel_size.log2()).ceil().max(0.0) as u32 + EXTRA_BITS;
+
+        let parse = |value: &str| FixedPoint::parse(value, bits)
+            .ok_or_else(|| format!("Can't parse '{}' as a decimal number!", value));
+
+        let c_real = parse(&deep_zoom.center_real)?;
+        let c_imaginary = parse(&deep_zoom.center_imaginary)?;
+        let bailout_squared = FixedPoint::parse(&format!("{:e}", config.bailout * config.bailout), bits)
+            .ok_or_else(|| format!("Can't use a bailout of {} for deep zooms!", config.bailout))?;
+
+        let mut z_real = FixedPoint::zero(bits);
+        let mut z_imaginary = FixedPoint::zero(bits);
+
+        let mut orbit = Vec::new();
+        orbit.push(Complex { real: 0.0, imaginary: 0.0 });
+
+        // A pixel's first value is `c` which is `Z_1`, so `max_i + 1` values are needed after `Z_0`.
+        for _iteration in 0..=config.max_i {
+
+            let real_squared = &z_real * &z_real;
+            let imaginary_squared = &z_imaginary * &z_imaginary;
+
+            // Stops once the reference escapes, pixels get rebased if they go past this point.
+            if &real_squared + &imaginary_squared > bailout_squared {
+                break;
+            }
+
+            let real_imaginary = &z_real * &z_imaginary;
+            z_imaginary = &(&real_imaginary + &real_imaginary) + &c_imaginary;
+            z_real = &(&real_squared - &imaginary_squared) + &c_real;
+
+            orbit.push(Complex {
+                real      : z_real.to_f64(),
+                imaginary : z_imaginary.to_f64(),
+            });
+        }
+
+        log::info!("Calculated reference orbit with {} bits of precision ({} iterations)", bits, orbit.len() - 1);
+
+        return Ok(Self {
+            orbit,
+            math_frame: deep_zoom.get_math_frame(config.size_x, config.size_y),
+            center_x: (config.size_x as f64 - 1.0) / 2.0,
+            center_y: (config.size_y as f64 - 1.0) / 2.0,
+            rebased_pixels: AtomicU64::new(0),
+            escape_function: get_escape(config),
+            orbit_trap: config.orbit_trap.as_ref().map(Trap::new).transpose()?,
+        });
+    }
+
+    /// Gets the offset of the pixel at (`x`, `y`) from the center of the image in math space.
+    pub fn get_delta_c(&self, x: f64, y: f64) -> Complex<f64> {
+        return self.math_frame.get_delta(x - self.center_x, y - self.center_y);
+    }
+
+    /// Gets the amount of pixels which had to be rebased to avoid glitches.
+    pub fn get_rebased_pixels(&self) -> u64 {
+        return self.rebased_pixels.load(Ordering::Relaxed);
+    }
+
+    /// Iterates the pixel which is `delta_c` away from the center of the image.
+    pub fn method(&self, delta_c: Complex<f64>, config: &Config) -> Sample {
+
+        let last = self.orbit.len() - 1;
+
+        // The pixel's first value is `c` which is `Z_1` (offset by `delta_c`)
+        let mut n = 1;
+        let mut delta = delta_c;
+        let mut z = self.orbit[n] + delta;
+        let mut old_z = z;
+        let mut previous_z = Complex { real: f64::INFINITY, imaginary: f64::INFINITY };
+        let mut rebased = false;
+
+        let limit = self.escape_function.get_limit(config);
+        let mut z_output = 0.0;
+
+        // The closest the orbit has gotten to the orbit trap
+        let mut trap = f64::INFINITY;
+
+        for _iteration in 0..config.max_i {
+            if self.escape_function.method_f64(z, previous_z, limit) { break }
+            previous_z = z;
+
+            // Rebases the pixel onto the start of the reference orbit
+            if n == last || z.norm_sqr() < delta.norm_sqr() {
+                rebased |= n != last;
+                delta = z;
+                n = 0;
+            }
+
+            let reference = self.orbit[n];
+            delta = (reference + reference) * delta + delta * delta + delta_c;
+            n += 1;
+            z = self.orbit[n] + delta;
+            if let Some(orbit_trap) = &self.orbit_trap {
+                trap = trap.min(orbit_trap.distance(z));
+            }
+
+            // Calculates Output
+            if !config.travel_distance {
+                z_output += 1.0;
+            } else {
+                z_output += (z - old_z).norm_sqr().sqrt();
+                old_z = z;
+            }
+        }
+
+        if rebased {
+            self.rebased_pixels.fetch_add(1, Ordering::Relaxed);
+        }
+
+        return Sample {
+            z_output,
+            z_norm: z.norm_sqr().sqrt(),
+            is_inside: z_output >= config.max_i as f64 && !config.travel_distance,
+            root: None,
+            distance: None,
+            normal: None,
+            trap: self.orbit_trap.as_ref().map(|_| trap),
+        };
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::DeepZoom;
+
+    fn get_deep_zoom_config(center_real: &str) -> Config {
+        return Config {
+            max_i: 64,
+            deep_zoom: Some(DeepZoom {
+                center_real: center_real.into(),
+                center_imaginary: "0.1".into(),
+                radius: 1e-3,
+                rotation: 0.0,
+            }),
+            ..Config::default()
+        };
+    }
+
+    #[test]
+    fn invalid_settings_are_errors() {
+        assert!(ReferenceOrbit::new(&Config::default()).is_err());
+        assert!(ReferenceOrbit::new(&get_deep_zoom_config("-0.5.1")).is_err());
+        assert!(ReferenceOrbit::new(&Config { gen_formula: "BS".into(), ..get_deep_zoom_config("-0.5") }).is_err());
+        assert!(ReferenceOrbit::new(&get_deep_zoom_config("-0.5")).is_ok());
+    }
+
+    #[test]
+    fn matches_direct_iteration() {
+        let config = Config { travel_distance: false, ..get_deep_zoom_config("-0.75") };
+        let reference_orbit = ReferenceOrbit::new(&config).unwrap();
+
+        // Pixels far from the set escape after the same amount of iterations as with plain f64 math
+        for delta_c in [Complex { real: 0.2, imaginary: 0.3 }, Complex { real: -1.5, imaginary: 0.0 }] {
+            let c = Complex { real: -0.75, imaginary: 0.1 } + delta_c;
+            let mut z = c;
+            let mut i = 0.0;
+            while !z.is_greater(2.0) && i < config.max_i as f64 {
+                z = z * z + c;
+                i += 1.0;
+            }
+            assert_eq!(reference_orbit.method(delta_c, &config).z_output, i);
+        }
+    }
+}
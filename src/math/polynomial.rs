@@ -0,0 +1,142 @@
+/*
+  File for the polynomials used by Newton type formulas.
+*/
+
+use num_traits::Float;
+
+use crate::structs::Complex;
+
+/// The most iterations used when searching for the roots of a polynomial.
+const MAX_ROOT_ITERATIONS: usize = 1000;
+
+/// A polynomial with real coefficients, stored from the highest degree to the constant.
+/// Ex: `[1.0, 0.0, 0.0, -1.0]` is `z^3 - 1`
+#[derive(Debug, Clone)]
+pub struct Polynomial {
+    coefficients: Vec<f64>,
+}
+
+impl Polynomial {
+
+    /// Creates a polynomial from its coefficients (highest degree first.)
+    /// Leading zeros are removed.
+    pub fn new(coefficients: &[f64]) -> Self {
+        let first = coefficients.iter().position(|v| *v != 0.0).unwrap_or(coefficients.len());
+        return Self {
+            coefficients: coefficients[first..].to_vec(),
+        };
+    }
+
+    /// Gets the degree of the polynomial (0 for constant polynomials.)
+    pub fn degree(&self) -> usize {
+        return self.coefficients.len().saturating_sub(1);
+    }
+
+    /// Evaluates the polynomial & its derivative at `z` using Horner's method.
+    pub fn evaluate<T: Float>(&self, z: Complex<T>) -> (Complex<T>, Complex<T>) {
+        let mut value = Complex::from_real(T::zero());
+        let mut derivative = Complex::from_real(T::zero());
+        for coefficient in &self.coefficients {
+            derivative = derivative * z + value;
+            value = value * z + Complex::from_real(T::from(*coefficient).unwrap());
+        }
+        return (value, derivative);
+    }
+
+    /// Gets the GLSL code for evaluating the polynomial into `p` & its derivative into `dp`.
+    /// This does the same steps as `evaluate`.
+    pub fn gpu_method(&self) -> String {
+        let mut code = String::from("Complex p = number(0.0);\n        Complex dp = number(0.0);");
+        for coefficient in &self.coefficients {
+            code += &format!(
+                "\n        dp = add(mult(dp, z), p);\n        p = add(mult(p, z), number({:?}));",
+                coefficient,
+            );
+        }
+        return code;
+    }
+
+    /// Finds all the (complex) roots of the polynomial with the Durand-Kerner method.
+    /// Returns an error message if the polynomial doesn't have any roots.
+    pub fn roots(&self) -> Result<Vec<Complex<f64>>, String> {
+
+        if self.degree() < 1 {
+            return Err("The polynomial needs to have a degree of at least 1 to have roots!".into());
+        }
+
+        // Makes the polynomial monic so the method converges
+        let monic = Polynomial::new(
+            &self.coefficients
+                .iter()
+                .map(|v| v / self.coefficients[0])
+                .collect::<Vec<f64>>()
+        );
+
+        // Starts at powers of a number that isn't real or a root of unity
+        let seed = Complex { real: 0.4, imaginary: 0.9 };
+        let mut roots: Vec<Complex<f64>> = (0..self.degree())
+            .map(|v| seed.powi(v as i32))
+            .collect();
+
+        for _iteration in 0..MAX_ROOT_ITERATIONS {
+            let mut largest_change: f64 = 0.0;
+
+            for i in 0..roots.len() {
+                let mut denominator = Complex::from_real(1.0);
+                for j in 0..roots.len() {
+                    if i != j {
+                        denominator = denominator * (roots[i] - roots[j]);
+                    }
+                }
+                let change = monic.evaluate(roots[i]).0 / denominator;
+                roots[i] = roots[i] - change;
+                largest_change = largest_change.max(change.abs());
+            }
+
+            if largest_change < 1e-14 {
+                break;
+            }
+        }
+
+        return Ok(roots);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate() {
+        // p(z) = 2z^2 - 3z + 1, p'(z) = 4z - 3
+        let polynomial = Polynomial::new(&[0.0, 2.0, -3.0, 1.0]);
+        assert_eq!(polynomial.degree(), 2);
+
+        let (p, dp) = polynomial.evaluate(Complex { real: 2.0, imaginary: 1.0 });
+        assert_eq!((p.real, p.imaginary), (1.0, 5.0));
+        assert_eq!((dp.real, dp.imaginary), (5.0, 4.0));
+    }
+
+    #[test]
+    fn roots_of_unity() {
+        let roots = Polynomial::new(&[1.0, 0.0, 0.0, -1.0]).roots().unwrap();
+        assert_eq!(roots.len(), 3);
+        for root in &roots {
+            assert!((root.abs() - 1.0).abs() < 1e-12);
+            assert!((root.powi(3) - Complex::from_real(1.0)).abs() < 1e-12);
+        }
+        // Every root is found once
+        for i in 0..roots.len() {
+            for j in 0..i {
+                assert!((roots[i] - roots[j]).abs() > 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn constant_has_no_roots() {
+        assert!(Polynomial::new(&[0.0, 3.0]).roots().is_err());
+        assert!(Polynomial::new(&[]).roots().is_err());
+    }
+}
@@ -0,0 +1,76 @@
+/*
+  File for the seeded random number generator.
+  This is kept in tree so renders which use random numbers are reproducible from their config.
+*/
+
+/// The amount the state moves forward every time a number is generated.
+const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// The SplitMix64 random number generator.
+/// Not suitable for cryptography, but fast & good enough for sampling.
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+
+    pub fn new(seed: u64) -> Self {
+        return Self { state: seed };
+    }
+
+    /// Moves the generator forward as if `count` numbers were generated.
+    /// This lets work be split up while still generating the same numbers as one generator would.
+    pub fn skip(mut self, count: u64) -> Self {
+        self.state = self.state.wrapping_add(GAMMA.wrapping_mul(count));
+        return self;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GAMMA);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        return z ^ (z >> 31);
+    }
+
+    /// Gets a random number in the range `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        // The top 53 bits fill the mantissa of an f64
+        return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_values() {
+        // Values from the reference implementation with a seed of 0
+        let mut random = SplitMix64::new(0);
+        assert_eq!(random.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(random.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+        assert_eq!(random.next_u64(), 0x06C4_5D18_8009_454F);
+    }
+
+    #[test]
+    fn skip_matches_generating() {
+        let mut generated = SplitMix64::new(42);
+        for _ in 0..10 {
+            generated.next_u64();
+        }
+        let mut skipped = SplitMix64::new(42).skip(10);
+        assert_eq!(generated.next_u64(), skipped.next_u64());
+    }
+
+    #[test]
+    fn floats_are_in_range() {
+        let mut random = SplitMix64::new(1);
+        for _ in 0..1000 {
+            let value = random.next_f64();
+            assert!((0.0..1.0).contains(&value));
+        }
+    }
+}
@@ -0,0 +1,184 @@
+/*
+  File for overriding a loaded config with the flags given on the command line.
+  Only flags which were actually given are applied, so everything else keeps the loaded value.
+*/
+
+use crate::cli::Args;
+use crate::structs::{Config, MathFrame, View};
+
+/// The flags which place the image with factors & offsets.
+const FRAME_FLAGS: [&str; 5] = ["factor_x", "factor_y", "offset_x", "offset_y", "stretch"];
+
+/// The flags which place the image by its center.
+const VIEW_FLAGS: [&str; 5] = ["center_real", "center_imaginary", "radius", "zoom", "rotation"];
+
+/// Overrides the settings of `config` with the flags for which `is_set` returns true.
+/// `cli_config` is the config made from the CLI arguments `args`. Settings of parts which aren't
+/// turned on in `config` (like `--trap-radius` without an orbit trap) are ignored.
+pub fn apply_overrides(config: &mut Config, cli_config: &Config, args: &Args, is_set: impl Fn(&str) -> bool) {
+    let old_size = (config.size_x, config.size_y);
+    let old_max_i = config.max_i;
+
+    // Flags which set a whole field of the config
+    macro_rules! copy_fields {
+        ($($flag:literal => $field:ident),* $(,)?) => {
+            $(if is_set($flag) { config.$field = cli_config.$field.clone(); })*
+        };
+    }
+
+    copy_fields!(
+        "pixels" => size_x, "pixels" => size_y,
+        "width" => size_x, "height" => size_y,
+        "size" => size_x, "size" => size_y,
+        "iterations" => max_i,
+        "samples" => samples,
+        "adaptive_samples" => adaptive_samples,
+        "adaptive_threshold" => adaptive_threshold,
+        "formula" => gen_formula,
+        "power" => power,
+        "polynomial" => polynomial,
+        "color" => color_formula,
+        "rate_of_color_change" => rate_of_color_change,
+        "shadow" => shadow_formula,
+        "palette" => palette,
+        "background" => background,
+        "foreground" => foreground,
+        "rgba" => rgba,
+        "save_method" => save_method,
+        "filename" => filename,
+        "julia" => c_init,
+        "deep_zoom" => deep_zoom,
+        "buddhabrot" => buddhabrot,
+        "anti_buddhabrot" => buddhabrot,
+        "orbit_trap" => orbit_trap,
+        "travel_distance" => travel_distance,
+        "smooth" => smooth,
+        "histogram" => histogram,
+        "bailout" => bailout,
+        "escape" => escape_formula,
+        "tolerance" => tolerance,
+        "distance_estimation" => distance_estimation,
+        "boundary_width" => boundary_width,
+        "light_angle" => light_angle,
+        "light_height" => light_height,
+        "gpu" => gpu,
+        "precision" => precision,
+        "strategy" => strategy,
+        "subdivision_guard" => subdivision_guard,
+        "chunk_size" => chunk_size,
+        "compression" => compression,
+        "compression_threads" => compression_threads,
+        "threads" => threads,
+        "read_config" => read_config,
+    );
+
+    // Flags which set part of a field
+    if let Some(palette) = &mut config.palette {
+        if is_set("palette_space") { palette.space = args.palette_space; }
+    }
+    if let Some(c_init) = &mut config.c_init {
+        if is_set("c_real") { c_init.real = args.c_real; }
+        if is_set("c_imaginary") { c_init.imaginary = args.c_imaginary; }
+    }
+    if let Some(buddhabrot) = &mut config.buddhabrot {
+        if is_set("orbits") { buddhabrot.orbits = args.orbits; }
+        if is_set("seed") { buddhabrot.seed = args.seed; }
+        if is_set("density_gamma") { buddhabrot.gamma = args.density_gamma; }
+        if let (true, Some(limits)) = (is_set("nebula"), &args.nebula) {
+            buddhabrot.channel_max_i = [limits[0], limits[1], limits[2]];
+        } else if buddhabrot.channel_max_i == [old_max_i; 3] {
+            // The channels follow the iteration count unless they were set separately
+            buddhabrot.channel_max_i = [config.max_i; 3];
+        }
+    }
+    if let Some(orbit_trap) = &mut config.orbit_trap {
+        if is_set("trap_real") { orbit_trap.center.real = args.trap_real; }
+        if is_set("trap_imaginary") { orbit_trap.center.imaginary = args.trap_imaginary; }
+        if is_set("trap_angle") { orbit_trap.angle = args.trap_angle; }
+        if is_set("trap_radius") { orbit_trap.radius = args.trap_radius; }
+        if is_set("trap_image") { orbit_trap.image = args.trap_image.clone(); }
+    }
+
+    // The image gets placed by the last of the frame, view & deep zoom settings
+    let radius = args.radius.or(args.zoom.map(|zoom| 2.0 / zoom));
+    let (steps_x, steps_y) = ((old_size.0.max(2) - 1) as f64, (old_size.1.max(2) - 1) as f64);
+    if FRAME_FLAGS.iter().any(|flag| is_set(flag)) {
+        // Flags which aren't given keep the area of the loaded frame
+        let frame = &config.math_frame;
+        let value = |flag: &str, cli_value: f64, loaded_value: f64| if is_set(flag) { cli_value } else { loaded_value };
+        config.math_frame = MathFrame::fit(
+            value("factor_x", args.factor_x, frame.factor_x * steps_x),
+            value("factor_y", args.factor_y, frame.factor_y * steps_y),
+            value("offset_x", args.offset_x, frame.offset_x),
+            value("offset_y", args.offset_y, frame.offset_y),
+            config.size_x,
+            config.size_y,
+            args.stretch,
+        );
+        config.view = None;
+    } else if let Some(deep_zoom) = &mut config.deep_zoom {
+        if let (true, Some(value)) = (is_set("center_real"), &args.center_real) { deep_zoom.center_real = value.clone(); }
+        if let (true, Some(value)) = (is_set("center_imaginary"), &args.center_imaginary) { deep_zoom.center_imaginary = value.clone(); }
+        if let (true, Some(value)) = (is_set("radius") || is_set("zoom"), radius) { deep_zoom.radius = value; }
+        if let (true, Some(value)) = (is_set("rotation"), args.rotation) { deep_zoom.rotation = value; }
+    } else if VIEW_FLAGS.iter().any(|flag| is_set(flag)) {
+        // Images placed with factors & offsets start from the center & height of their frame
+        let frame = &config.math_frame;
+        let mut view = config.view.clone().unwrap_or_else(|| View {
+            center: frame.get_point(steps_x / 2.0, steps_y / 2.0),
+            radius: frame.get_delta(0.0, steps_y).abs() / 2.0,
+            rotation: 0.0,
+        });
+        if let Some(cli_view) = &cli_config.view {
+            if is_set("center_real") { view.center.real = cli_view.center.real; }
+            if is_set("center_imaginary") { view.center.imaginary = cli_view.center.imaginary; }
+            if is_set("radius") || is_set("zoom") { view.radius = cli_view.radius; }
+            if is_set("rotation") { view.rotation = cli_view.rotation; }
+        }
+        config.view = Some(view);
+    } else if config.view.is_none() && (config.size_x, config.size_y) != old_size {
+        // Keeps showing the same area at the new size
+        config.math_frame = config.math_frame.resize(old_size, (config.size_x, config.size_y));
+    }
+
+    if (config.size_x, config.size_y) != old_size && !is_set("chunk_size") {
+        config.chunk_size = config.size_x.max(config.size_y) as u64;
+    }
+
+    config.rgba |= config.gpu; // forces rgba if using gpu
+    config.logs = cli_config.logs;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn only_given_flags_override() {
+        let args = Args::parse_from(["kyros", "-y", "--iterations", "500", "--trap-radius", "2"]);
+        let cli_config = Config { max_i: 500, samples: 4, ..Config::default() };
+        let mut config = Config { max_i: 1000, samples: 2, smooth: true, ..Config::default() };
+
+        apply_overrides(&mut config, &cli_config, &args, |id| id == "iterations" || id == "trap_radius");
+        assert_eq!(config.max_i, 500);
+        // Flags which weren't given keep the loaded value
+        assert_eq!(config.samples, 2);
+        assert!(config.smooth);
+        // Settings of features which aren't turned on are ignored
+        assert!(config.orbit_trap.is_none());
+    }
+
+    #[test]
+    fn resizing_keeps_the_area() {
+        let args = Args::parse_from(["kyros", "-y"]);
+        let cli_config = Config { size_x: 1023, size_y: 1023, ..Config::default() };
+        let mut config = Config::default();
+        let corner = config.math_frame.get_point(511.0, 511.0);
+
+        apply_overrides(&mut config, &cli_config, &args, |id| id == "pixels");
+        let new_corner = config.math_frame.get_point(1022.0, 1022.0);
+        assert!((new_corner.real - corner.real).abs() < 1e-12 && (new_corner.imaginary - corner.imaginary).abs() < 1e-12);
+        assert_eq!(config.chunk_size, 1023);
+    }
+}
@@ -0,0 +1,237 @@
+/*
+  File for the raw output format.
+  Instead of colors this stores the escape values of each pixel, so images can be recolored
+  without generating them again.
+
+  Layout (little endian):
+    8 bytes     The magic bytes `KYROSRAW`
+    4 bytes     The version of the format
+    4 bytes     The length of the config
+    n bytes     The config used for generation (JSON)
+    33 bytes    For each pixel: z_output (f64), |z| (f32), distance estimate (f64), normal (2 f32),
+                orbit trap distance (f32) & flags (u8)
+
+  Distance estimates, normals & orbit trap distances are NaN for pixels which don't have them.
+  The lowest bit of the flags is set for pixels inside the set. The other bits hold the index of
+  the root the pixel converged to plus one (0 if it didn't converge to a root.)
+*/
+
+use std::error::Error;
+use std::fs;
+
+use crate::colors::histogram::Histogram;
+use crate::colors::painter::Painter;
+use crate::colors::shadows::get_shadow;
+use crate::math::formula::get_formula;
+use crate::save::get_save_method;
+use crate::structs::{Complex, Config, Sample};
+
+/// The bytes every raw file starts with.
+const MAGIC: &[u8; 8] = b"KYROSRAW";
+
+/// The version of the format, this changes whenever the layout does.
+const VERSION: u32 = 2;
+
+/// The amount of bytes each pixel takes up.
+pub const BYTES_PER_PIXEL: usize = 33;
+
+/// The extension used for raw files.
+pub const EXTENSION: &str = "kyr";
+
+/// Writes the escape values of `sample` into `pixel`.
+pub fn encode_sample(sample: &Sample, pixel: &mut [u8]) {
+    let root = sample.root.map_or(0, |v| (v + 1).min(127) as u8);
+    pixel[0..8].copy_from_slice(&sample.z_output.to_le_bytes());
+    pixel[8..12].copy_from_slice(&(sample.z_norm as f32).to_le_bytes());
+    pixel[12..20].copy_from_slice(&sample.distance.unwrap_or(f64::NAN).to_le_bytes());
+    let normal = sample.normal.map_or([f32::NAN; 2], |v| [v.real as f32, v.imaginary as f32]);
+    pixel[20..24].copy_from_slice(&normal[0].to_le_bytes());
+    pixel[24..28].copy_from_slice(&normal[1].to_le_bytes());
+    pixel[28..32].copy_from_slice(&(sample.trap.unwrap_or(f64::NAN) as f32).to_le_bytes());
+    pixel[32] = sample.is_inside as u8 | root << 1;
+}
+
+/// Reads the escape values of a pixel back into a sample.
+pub fn decode_sample(pixel: &[u8]) -> Sample {
+    let read_f32 = |start: usize| f32::from_le_bytes(pixel[start..start + 4].try_into().unwrap()) as f64;
+    let read_f64 = |start: usize| f64::from_le_bytes(pixel[start..start + 8].try_into().unwrap());
+    let root = pixel[32] >> 1;
+    let normal = Complex { real: read_f32(20), imaginary: read_f32(24) };
+    return Sample {
+        z_output: read_f64(0),
+        z_norm: read_f32(8),
+        is_inside: pixel[32] & 1 == 1,
+        root: root.checked_sub(1).map(|v| v as usize),
+        distance: Some(read_f64(12)).filter(|v| !v.is_nan()),
+        normal: Some(normal).filter(|v| !v.real.is_nan()),
+        trap: Some(read_f32(28)).filter(|v| !v.is_nan()),
+    };
+}
+
+/// Writes the header & pixels (encoded with `encode_sample`) to `{config.filename}.kyr`.
+pub fn write(pixels: &[u8], config: &Config) -> Result<(), Box<dyn Error>> {
+    let config_json = serde_json::to_vec(config)?;
+
+    let mut data = Vec::with_capacity(16 + config_json.len() + pixels.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&(config_json.len() as u32).to_le_bytes());
+    data.extend_from_slice(&config_json);
+    data.extend_from_slice(pixels);
+
+    fs::write(format!("{}.{}", config.filename, EXTENSION), data)?;
+    return Ok(());
+}
+
+/// Reads a raw file.
+/// Returns the config used for generation & the encoded pixels.
+pub fn read(filename: &str) -> Result<(Config, Vec<u8>), Box<dyn Error>> {
+    let data = fs::read(filename).map_err(|e| format!("Can't open file `{}`: {}", filename, e))?;
+
+    if data.len() < 16 || &data[0..8] != MAGIC {
+        return Err(format!("`{}` isn't a raw kyros file!", filename).into());
+    }
+    let version = u32::from_le_bytes(data[8..12].try_into().unwrap());
+    if version != VERSION {
+        return Err(format!("`{}` uses version {} of the raw format, only version {} is supported!", filename, version, VERSION).into());
+    }
+
+    let config_length = u32::from_le_bytes(data[12..16].try_into().unwrap()) as usize;
+    let config_json = data.get(16..16 + config_length).ok_or("The raw file ends before its config!")?;
+    let config: Config = serde_json::from_slice(config_json)?;
+
+    let pixels = data[16 + config_length..].to_vec();
+    let expected_length = config.size_x as usize * config.size_y as usize * BYTES_PER_PIXEL;
+    if pixels.len() != expected_length {
+        return Err(format!("The raw file has {} bytes of pixels but a {}x{} image needs {}!", pixels.len(), config.size_x, config.size_y, expected_length).into());
+    }
+
+    return Ok((config, pixels));
+}
+
+/// Colors the pixels of a raw file with the color settings of `settings` & saves them with its save method.
+/// Everything else (like the size & formula of the image) comes from the config stored in the file.
+pub fn recolor(filename: &str, settings: &Config) -> Result<(), Box<dyn Error>> {
+    let (stored_config, pixels) = read(filename)?;
+
+    let config = Config {
+        color_formula: settings.color_formula.clone(),
+        rate_of_color_change: settings.rate_of_color_change,
+        shadow_formula: settings.shadow_formula.clone(),
+        palette: settings.palette.clone(),
+        background: settings.background.clone(),
+        foreground: settings.foreground.clone(),
+        smooth: settings.smooth,
+        histogram: settings.histogram,
+        distance_estimation: settings.distance_estimation,
+        boundary_width: settings.boundary_width,
+        light_angle: settings.light_angle,
+        light_height: settings.light_height,
+        rgba: settings.rgba,
+        save_method: settings.save_method.clone(),
+        filename: settings.filename.clone(),
+        logs: settings.logs,
+        ..stored_config
+    };
+
+    if config.save_method == "RAW" {
+        return Err("Recolored images can't be saved with the RAW save method!".into());
+    }
+
+    // Distance estimates & normals are only stored if the derivative was tracked during generation
+    let has_derivative = stored_config.distance_estimation || get_shadow(&stored_config.shadow_formula).needs_derivative();
+    if !has_derivative && (config.distance_estimation || get_shadow(&config.shadow_formula).needs_derivative()) {
+        return Err(format!(
+            "`{}` doesn't store distance estimates, generate it with --distance-estimation to recolor it with --distance-estimation or the NORMAL shadow!",
+            filename,
+        ).into());
+    }
+
+    let samples: Vec<Sample> = pixels.chunks(BYTES_PER_PIXEL).map(decode_sample).collect();
+
+    let mut painter = Painter::new(&config, get_formula(&config).get_roots()?.len());
+    // Every pixel is known so the distribution comes from the whole image instead of a preview
+    if config.histogram {
+        painter.set_histogram(Histogram::new(
+            samples
+                .iter()
+                .filter(|sample| !sample.is_inside && sample.z_output != 0.0)
+                .map(|sample| sample.get_color_value(&config))
+                .collect()
+        ));
+    }
+
+    let bytes_per_pixel = 3 + config.rgba as usize;
+    let mut img = Vec::with_capacity(samples.len() * bytes_per_pixel);
+    for sample in samples.iter() {
+        img.extend_from_slice(&painter.color(sample)[0..bytes_per_pixel]);
+    }
+
+    log::info!("Recolored {}x{} pixels from `{}`", config.size_x, config.size_y, filename);
+    return get_save_method(&config.save_method).method(img.as_slice(), &config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_round_trip() {
+        let sample = Sample {
+            z_output: 123.456,
+            z_norm: 2.5,
+            is_inside: false,
+            root: Some(2),
+            distance: Some(1e-3),
+            normal: Some(Complex { real: 0.6, imaginary: -0.8 }),
+            trap: Some(0.25),
+        };
+        let mut pixel = [0u8; BYTES_PER_PIXEL];
+        encode_sample(&sample, &mut pixel);
+        let decoded = decode_sample(&pixel);
+
+        assert_eq!(decoded.z_output, 123.456);
+        assert_eq!(decoded.z_norm, 2.5);
+        assert!(!decoded.is_inside);
+        assert_eq!(decoded.root, Some(2));
+        assert_eq!(decoded.distance, Some(1e-3));
+        let normal = decoded.normal.unwrap();
+        assert!((normal.real - 0.6).abs() < 1e-6 && (normal.imaginary + 0.8).abs() < 1e-6);
+        assert_eq!(decoded.trap, Some(0.25));
+    }
+
+    #[test]
+    fn missing_values_round_trip() {
+        let sample = Sample { z_output: 64.0, is_inside: true, ..Sample::default() };
+        let mut pixel = [0u8; BYTES_PER_PIXEL];
+        encode_sample(&sample, &mut pixel);
+        let decoded = decode_sample(&pixel);
+
+        assert!(decoded.is_inside);
+        assert_eq!(decoded.root, None);
+        assert_eq!(decoded.distance, None);
+        assert!(decoded.normal.is_none());
+        assert_eq!(decoded.trap, None);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let filename = std::env::temp_dir().join(format!("kyros_raw_test_{}", std::process::id()));
+        let config = Config { size_x: 2, size_y: 1, filename: filename.to_string_lossy().into(), ..Config::default() };
+
+        let mut pixels = vec![0u8; 2 * BYTES_PER_PIXEL];
+        encode_sample(&Sample { z_output: 5.0, ..Sample::default() }, &mut pixels[0..BYTES_PER_PIXEL]);
+        write(&pixels, &config).unwrap();
+
+        let path = format!("{}.{}", config.filename, EXTENSION);
+        let (stored_config, stored_pixels) = read(&path).unwrap();
+        assert_eq!((stored_config.size_x, stored_config.size_y), (2, 1));
+        assert_eq!(stored_pixels, pixels);
+        assert_eq!(decode_sample(&stored_pixels[0..BYTES_PER_PIXEL]).z_output, 5.0);
+
+        // Files of other formats & cut off files are rejected
+        std::fs::write(&path, b"NOTKYROS").unwrap();
+        assert!(read(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+/*
+  File for regenerating images from the config saved in them.
+  PNG files store the config used for generation in a zTXt chunk labeled `kyros_config` &
+  raw files store it in their header (see raw.rs.)
+*/
+
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+use png::Decoder;
+
+use crate::raw;
+use crate::structs::Config;
+
+/// Reads the config an image was generated with.
+/// Configs saved before a setting existed use its default value.
+pub fn read_saved_config(filename: &str) -> Result<Config, Box<dyn Error>> {
+    let extension = Path::new(filename).extension().and_then(|v| v.to_str()).unwrap_or_default();
+    if extension.eq_ignore_ascii_case(raw::EXTENSION) {
+        let (config, _pixels) = raw::read(filename)?;
+        return Ok(config);
+    }
+
+    let file = File::open(filename).map_err(|e| format!("Can't open file `{}`: {}", filename, e))?;
+    let reader = Decoder::new(file)
+        .read_info()
+        .map_err(|e| format!("Can't parse the PNG headers of `{}`: {}", filename, e))?;
+
+    let mut chunk = reader.info().compressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == "kyros_config")
+        .ok_or(format!("Can't find the compressed chunk labeled `kyros_config` in `{}`!", filename))?
+        .clone();
+    chunk.decompress_text().map_err(|e| format!("Can't decompress the config of `{}`: {}", filename, e))?;
+
+    let text = chunk.get_text().map_err(|e| format!("Can't read the config of `{}`: {}", filename, e))?;
+    let config = serde_json::from_str(&text).map_err(|e| format!("Can't parse the config of `{}`: {}", filename, e))?;
+    return Ok(config);
+}
+
+/// Gets the filename (without extension) images reproduced from `filename` are saved to by default,
+/// which is next to the source image so it doesn't get overwritten.
+pub fn get_default_filename(filename: &str) -> String {
+    let path = Path::new(filename);
+    let stem = path.file_stem().and_then(|v| v.to_str()).unwrap_or("image");
+    return path.with_file_name(format!("{}_reproduced", stem)).to_string_lossy().into_owned();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_filename() {
+        assert_eq!(get_default_filename("image.png"), "image_reproduced");
+        assert_eq!(get_default_filename("renders/zoom.kyr"), Path::new("renders").join("zoom_reproduced").to_string_lossy());
+    }
+
+    #[test]
+    fn raw_configs() {
+        let filename = std::env::temp_dir().join(format!("kyros_reproduce_test_{}", std::process::id()));
+        let config = Config { size_x: 3, size_y: 2, max_i: 1234, filename: filename.to_string_lossy().into(), ..Config::default() };
+        raw::write(&vec![0u8; 6 * raw::BYTES_PER_PIXEL], &config).unwrap();
+
+        let path = format!("{}.{}", config.filename, raw::EXTENSION);
+        let saved_config = read_saved_config(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((saved_config.size_x, saved_config.size_y, saved_config.max_i), (3, 2, 1234));
+
+        assert!(read_saved_config(&path).is_err());
+    }
+}
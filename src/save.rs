@@ -2,6 +2,7 @@
 #![allow(unused_imports)]
 
 use crate::cli::Args;
+use crate::raw;
 use crate::structs::Config;
 use std::ops::Deref;
 use std::io::Cursor;
@@ -21,6 +22,8 @@ use image::codecs::png::PngEncoder;
 pub trait Save {
     fn get_alias(&self) -> String;
     fn get_description(&self) -> String;
+    /// Gets the file the image gets saved to (`None` if it isn't saved to a file.)
+    fn get_output(&self, config: &Config) -> Option<String>;
     fn method(&self, image_buffer: &[u8], config: &Config) -> Result<(), Box<dyn Error>>;
 }
 
@@ -29,6 +32,7 @@ pub struct PNG {}
 impl Save for PNG {
     fn get_alias(&self) -> String { "PNG".into() }
     fn get_description(&self) -> String { "Saves Image as PNG.".into() }
+    fn get_output(&self, config: &Config) -> Option<String> { Some(format!("{}.png", config.filename)) }
     fn method(&self, image_buffer: &[u8], config: &Config) -> Result<(), Box<dyn Error>> {
         // save_buffer(format!("{}.png", config.filename), image_buffer, image_buffer.width(), image_buffer.height());
 
@@ -59,6 +63,7 @@ pub struct TIFF {}
 impl Save for TIFF {
     fn get_alias(&self) -> String {"TIFF".into()}
     fn get_description(&self) -> String {"Saves Image as TIFF".into()}
+    fn get_output(&self, config: &Config) -> Option<String> { Some(format!("{}.tiff", config.filename)) }
     fn method(&self, image_buffer: &[u8], config: &Config) -> Result<(), Box<dyn Error>> {
 
         let outfile = format!("{}.tiff", config.filename);
@@ -85,6 +90,7 @@ pub struct B64 {}
 impl Save for B64 {
     fn get_alias(&self) -> String { "B64".into() }
     fn get_description(&self) -> String { "Sends base-64 encoded PNG image to std-out.".into() }
+    fn get_output(&self, _config: &Config) -> Option<String> { None }
     fn method(&self, image_buffer: &[u8], config: &Config) -> Result<(), Box<dyn Error>> {
         let mut png_buf = Vec::new();
         {
@@ -108,31 +114,50 @@ impl Save for B64 {
     }
 }
 
+pub struct RAW {}
+
+impl Save for RAW {
+    fn get_alias(&self) -> String { "RAW".into() }
+    fn get_description(&self) -> String { "Saves the escape values of each pixel for recoloring with --recolor (CPU only.)".into() }
+    fn get_output(&self, config: &Config) -> Option<String> { Some(format!("{}.{}", config.filename, raw::EXTENSION)) }
+    fn method(&self, image_buffer: &[u8], config: &Config) -> Result<(), Box<dyn Error>> {
+        return raw::write(image_buffer, config);
+    }
+}
+
 struct MOCK {}
 
 impl Save for MOCK {
     fn get_alias(&self) -> String { return "MOCK".to_string(); }
     fn get_description(&self) -> String { return "A Save method meant to be used for testing, doesn't actually save and data.".to_string(); }
+    fn get_output(&self, _config: &Config) -> Option<String> { return None; }
     fn method(&self, _image_buffer: &[u8], _config: &Config) -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 }
 
 /// Function for getting the method for saving images from config
+/// Exits with the list of save methods if it can't be found.
 pub fn get_save_method(save_method: &str) -> &dyn Save {
+    return try_get_save_method(save_method).unwrap_or_else(|e| Args::command().error(ErrorKind::InvalidValue, e).exit());
+}
+
+/// Same as `get_save_method` but returns the error message instead of exiting.
+pub fn try_get_save_method(save_method: &str) -> Result<&'static dyn Save, String> {
 
     // Makes array of methods which can be used
-    let methods: Vec<&dyn Save> = vec![
+    let methods: Vec<&'static dyn Save> = vec![
         &PNG{},
         &B64{},
         &TIFF{},
+        &RAW{},
         &MOCK{},
     ];
 
     // Iterates through methods to find one that matches
     for method in methods.clone() {
         if method.get_alias() == save_method.to_string() {
-            return method;
+            return Ok(method);
         }
     }
 
@@ -142,9 +167,6 @@ pub fn get_save_method(save_method: &str) -> &dyn Save {
         .collect::<Vec<String>>()
         .join("\n");
 
-    // If not found throw error
-    Args::command().error(
-        ErrorKind::InvalidValue,
-        format!("Save method '{}' not found!\n\nAllowed save methods:\n{}", save_method, saves_string)
-    ).exit();
+    // If not found return error
+    return Err(format!("Save method '{}' not found!\n\nAllowed save methods:\n{}", save_method, saves_string));
 }
@@ -8,32 +8,70 @@ File for containing the logic for the Complex Struct
 extern crate csscolorparser;
 
 use crate::cli::default_level_filter;
+use crate::colors::palette::Palette;
+use crate::colors::color::try_get_color;
+use crate::colors::shadows::try_get_shadow;
+use crate::math::escape::try_get_escape;
+use crate::math::fixed_point::FixedPoint;
+use crate::math::formula::try_get_formula;
+use crate::math::orbit_trap::Trap;
+use crate::math::polynomial::Polynomial;
+use crate::save::try_get_save_method;
 
-use std::{ops::{ Add, Mul, Sub}, str::FromStr, u64};
+use std::{ops::{ Add, Div, Mul, Neg, Sub}, str::FromStr, u64};
 
+use clap::error::ErrorKind;
+use clap::ValueEnum;
 use log::LevelFilter;
+use num_traits::Float;
 use serde::{Deserialize, Serialize};
 use vulkano::buffer::BufferContents;
 
 /// Main object for defining generation configuration.
+/// Missing fields use their default values so configs saved by older versions can still be loaded,
+/// unknown fields are rejected so typos in config files don't get ignored.
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub c_init:          Option<Complex>, // Initial C value for when swap_zc is used
+    pub c_init:     Option<Complex<f64>>, // Initial C value for when swap_zc is used
     pub size_x:                      u32, // Sets Image Width
     pub size_y:                      u32, // Sets Image Height
     pub max_i:                       u64, // Sets Maximum Iterations for Generator
+    pub samples:                     u32, // Sets the amount of samples per pixel (for anti-aliasing)
+    pub adaptive_samples:            u32, // Sets the amount of samples for pixels refined by adaptive supersampling (0 turns it off)
+    pub adaptive_threshold:          f64, // Sets how much a pixel's value has to differ from its neighbours to be refined
     pub gen_formula:              String, // Specifies Formula for Generator
+    pub power:                       f64, // Specifies the power used by the MULTI formula
+    pub polynomial:             Vec<f64>, // Specifies the polynomial coefficients used by Newton type formulas (highest degree first)
     pub color_formula:            String, // Specifies Formula for Colors
     pub rate_of_color_change:        f64, // Specifies the rate color changes for the color_formula
     pub shadow_formula:           String, // Specifies Formula for Shadows
+    pub palette:         Option<Palette>, // Specifies the gradient palette used instead of hue rotation
     pub background:csscolorparser::Color, // Specifies the background to use for the image
     pub foreground:csscolorparser::Color, // Specifies the foreground to use for the image
     pub travel_distance:            bool, // Specifies if the output color value should be based on travel distance
+    pub smooth:                     bool, // Specifies if the iteration count should be smoothed (normalized iteration count)
+    pub histogram:                  bool, // Specifies if color values should be mapped through the distribution of the image (histogram coloring)
+    pub bailout:                     f64, // Specifies the radius pixels have to pass to escape
+    pub escape_formula:   Option<String>, // Specifies the escape condition (uses the generation formula's default if not set)
+    pub tolerance:                   f64, // Specifies how little z has to move to count as converged
+    pub distance_estimation:        bool, // Specifies if pixels close to the set (using distance estimation) should use the foreground color
+    pub boundary_width:              f64, // Specifies how close (in pixels) a pixel has to be to the set to count as the boundary
+    pub light_angle:                 f64, // Specifies the angle (in degrees) the light comes from for the NORMAL shadow
+    pub light_height:                f64, // Specifies the height of the light for the NORMAL shadow
     pub rgba:                       bool, // Specifies if the image should be rgba or not
     pub gpu:                        bool, // Specifies if image should be generated on the GPU
     pub chunk_size:                  u64, // Specifies the amount of pixels per chunk (width x height)
     pub compression:                 u32, // Specifies the amount of compression to apply to the image.
     pub compression_threads:         u32, // Specifies the amount of threads to use in compression.
+    pub threads:                     u32, // Specifies the amount of threads to use for CPU generation (0 uses every available core.)
+    pub precision:             Precision, // Specifies the float type to use for generation math.
+    pub strategy:               Strategy, // Specifies the way the CPU renderer decides which pixels to generate.
+    pub subdivision_guard:          bool, // Specifies if subdivision should check the middle of tiles before filling them in.
+    pub deep_zoom:      Option<DeepZoom>, // Specifies the center of the image for perturbation based deep zooms.
+    pub buddhabrot:   Option<Buddhabrot>, // Specifies the settings for orbit density rendering.
+    pub view:               Option<View>, // Specifies the center, radius & rotation the math frame was made from.
+    pub orbit_trap:   Option<OrbitTrap>, // Specifies the trap orbits are compared against for orbit trap coloring.
     pub read_config:      Option<String>, // Specifies if the entire program should just read the config from file.
     pub save_method:              String, // Specifies the way the image should be saved
     pub filename:                 String, // Specifies the filename of the image
@@ -50,20 +88,36 @@ impl Default for Config {
             size_x: 512,
             size_y: 512,
             max_i: 1024,
+            samples: 1,
+            adaptive_samples: 0,
+            adaptive_threshold: 2.0,
             gen_formula: "SD".to_string(),
+            power: 2.0,
+            polynomial: vec![1.0, 0.0, 0.0, -1.0],
             color_formula: "ROTATIONAL".to_string(),
             rate_of_color_change: 9.0,
+            palette: None,
             background: csscolorparser::Color::from_str("transparent").unwrap(),
             foreground: csscolorparser::Color::from_str("black").unwrap(),
             chunk_size: 16384,
             compression: 9,
             compression_threads: 100,
+            threads: 0,
+            precision: Precision::F32,
+            strategy: Strategy::Pixels,
+            subdivision_guard: false,
+            deep_zoom: None,
+            buddhabrot: None,
+            view: None,
+            orbit_trap: None,
             filename: "out".to_string(),
             gpu: true,
             logs: default_level_filter(),
             math_frame: MathFrame {
                 factor_x: 4.0,
                 factor_y: 4.0,
+                shear_x: 0.0,
+                shear_y: 0.0,
                 offset_x: -2.0,
                 offset_y: -2.0,
             },
@@ -72,25 +126,401 @@ impl Default for Config {
             save_method: "MOCK".to_string(),
             shadow_formula: "NONE".to_string(),
             travel_distance: true,
+            smooth: false,
+            histogram: false,
+            bailout: 2.0,
+            escape_formula: None,
+            tolerance: 1e-6,
+            distance_estimation: false,
+            boundary_width: 1.0,
+            light_angle: 45.0,
+            light_height: 1.5,
         };
 
     }
 }
 
+impl Config {
+
+    /// Gets the amount of lines that fit in a chunk of `chunk_size` x `chunk_size` pixels.
+    /// This is always at least one line and at most the height of the image.
+    pub fn lines_per_chunk(&self) -> u32 {
+        // Uses higher precision for inner calculation because of overflow worries.
+        return (self.chunk_size.pow(2) / self.size_x as u64).clamp(1, self.size_y as u64) as u32;
+    }
+
+    /// Sets the math frame from the deep zoom or view settings (if either is set.)
+    pub fn update_math_frame(&mut self) {
+        if let Some(deep_zoom) = &self.deep_zoom {
+            self.math_frame = deep_zoom.get_math_frame(self.size_x, self.size_y);
+        } else if let Some(view) = &self.view {
+            self.math_frame = view.get_math_frame(self.size_x, self.size_y);
+        }
+    }
+
+    /// Checks that the settings can be generated together (& loads the orbit trap image to check it.)
+    /// Returns the kind of CLI error & a message for the first problem found.
+    pub fn validate(&self) -> Result<(), (ErrorKind, String)> {
+
+        // Names which aren't found (checked here so batches can skip the job instead of exiting)
+        try_get_formula(self).map_err(|e| (ErrorKind::InvalidValue, e))?;
+        try_get_color(&self.color_formula).map_err(|e| (ErrorKind::InvalidValue, e))?;
+        try_get_shadow(&self.shadow_formula).map_err(|e| (ErrorKind::InvalidValue, e))?;
+        try_get_escape(self).map_err(|e| (ErrorKind::InvalidValue, e))?;
+        try_get_save_method(&self.save_method).map_err(|e| (ErrorKind::InvalidValue, e))?;
+
+        // Newton type formulas divide by the derivative of the polynomial, which is 0 for constants
+        if matches!(self.gen_formula.as_str(), "NEWTON" | "NOVA") && Polynomial::new(&self.polynomial).degree() < 1 {
+            return Err((
+                ErrorKind::InvalidValue,
+                format!("The polynomial of the '{}' formula needs to have a degree of at least 1!", self.gen_formula),
+            ));
+        }
+
+        // Config files can hold `nan` & `inf` (in TOML) which the CLI flags don't allow
+        let mut values = vec![
+            ("adaptive_threshold", self.adaptive_threshold),
+            ("power", self.power),
+            ("rate_of_color_change", self.rate_of_color_change),
+            ("bailout", self.bailout),
+            ("tolerance", self.tolerance),
+            ("boundary_width", self.boundary_width),
+            ("light_angle", self.light_angle),
+            ("light_height", self.light_height),
+            ("math_frame.factor_x", self.math_frame.factor_x),
+            ("math_frame.factor_y", self.math_frame.factor_y),
+            ("math_frame.shear_x", self.math_frame.shear_x),
+            ("math_frame.shear_y", self.math_frame.shear_y),
+            ("math_frame.offset_x", self.math_frame.offset_x),
+            ("math_frame.offset_y", self.math_frame.offset_y),
+        ];
+        values.extend(self.polynomial.iter().map(|v| ("polynomial", *v)));
+        if let Some(c_init) = &self.c_init {
+            values.extend([("c_init.real", c_init.real), ("c_init.imaginary", c_init.imaginary)]);
+        }
+        if let Some(view) = &self.view {
+            values.extend([("view.center.real", view.center.real), ("view.center.imaginary", view.center.imaginary)]);
+            values.extend([("view.radius", view.radius), ("view.rotation", view.rotation)]);
+        }
+        if let Some(deep_zoom) = &self.deep_zoom {
+            values.extend([("deep_zoom.radius", deep_zoom.radius), ("deep_zoom.rotation", deep_zoom.rotation)]);
+        }
+        if let Some(orbit_trap) = &self.orbit_trap {
+            values.extend([("orbit_trap.center.real", orbit_trap.center.real), ("orbit_trap.center.imaginary", orbit_trap.center.imaginary)]);
+            values.extend([("orbit_trap.angle", orbit_trap.angle), ("orbit_trap.radius", orbit_trap.radius)]);
+        }
+        if let Some(buddhabrot) = &self.buddhabrot {
+            values.push(("buddhabrot.gamma", buddhabrot.gamma));
+        }
+        if let Some(palette) = &self.palette {
+            values.extend(palette.stops.iter().map(|v| ("palette.stops.position", v.position)));
+        }
+        if let Some((name, value)) = values.iter().find(|v| !v.1.is_finite()) {
+            return Err((ErrorKind::InvalidValue, format!("'{}' has to be a finite number (got {})!", name, value)));
+        }
+
+        // The same conflicts as the CLI flags, config files & batch jobs don't go through clap
+        let conflicts = [
+            (self.gpu && self.deep_zoom.is_some(), "--gpu", "--deep-zoom"),
+            (self.gpu && self.adaptive_samples > 0, "--gpu", "--adaptive-samples"),
+            (self.gpu && self.strategy != Strategy::Pixels, "--gpu", "--strategy"),
+            (self.gpu && self.buddhabrot.is_some(), "--gpu", "--buddhabrot"),
+            (self.deep_zoom.is_some() && self.c_init.is_some(), "--deep-zoom", "--julia"),
+            (self.buddhabrot.is_some() && self.c_init.is_some(), "--buddhabrot", "--julia"),
+            (self.buddhabrot.is_some() && self.deep_zoom.is_some(), "--buddhabrot", "--deep-zoom"),
+            (self.buddhabrot.is_some() && self.orbit_trap.is_some(), "--buddhabrot", "--orbit-trap"),
+        ];
+        if let Some((_, first, second)) = conflicts.iter().find(|v| v.0) {
+            return Err((
+                ErrorKind::ArgumentConflict,
+                format!("The argument '{}' cannot be used with '{}'", first, second),
+            ));
+        }
+
+        if let Some(deep_zoom) = &self.deep_zoom {
+            if self.gen_formula != "SD" {
+                return Err((
+                    ErrorKind::ArgumentConflict,
+                    format!("Deep zooms are only supported for the 'SD' formula! (Got formula '{}')", self.gen_formula),
+                ));
+            }
+            for value in [&deep_zoom.center_real, &deep_zoom.center_imaginary] {
+                if FixedPoint::parse(value, 64).is_none() {
+                    return Err((ErrorKind::InvalidValue, format!("Can't parse '{}' as a decimal number!", value)));
+                }
+            }
+        }
+
+        // Raw output needs the escape values of each pixel, which only per pixel CPU generation has
+        if self.save_method == "RAW" && (self.gpu || self.buddhabrot.is_some()) {
+            return Err((
+                ErrorKind::ArgumentConflict,
+                "The RAW save method only works with per pixel CPU generation (not --gpu or --buddhabrot)!".into(),
+            ));
+        }
+
+        // The GPU generates chunks of `chunk_size` x `chunk_size` pixels which have to fit at least one line
+        if self.gpu && self.chunk_size.pow(2) < self.size_x as u64 {
+            return Err((
+                ErrorKind::InvalidValue,
+                format!("A chunk size of {0} ({0}x{0} pixels) can't fit a line of the image ({1} pixels) when generating on the GPU!", self.chunk_size, self.size_x),
+            ));
+        }
+
+        if let Some(orbit_trap) = &self.orbit_trap {
+            Trap::new(orbit_trap).map_err(|e| (ErrorKind::InvalidValue, e))?;
+        }
+
+        return Ok(());
+    }
+
+}
+
+/// The float type used for the generation math.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    /// Single precision floats. These are the fastest and are supported by every GPU.
+    #[default]
+    F32,
+    /// Double precision floats. These allow for deeper zooms but require `shader_float64`
+    /// support when generating on the GPU.
+    F64,
+}
+
+/// The way the CPU renderer decides which pixels to generate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Generates every pixel.
+    #[default]
+    Pixels,
+    /// Splits the image into tiles & only generates their borders (Mariani-Silver.) Tiles whose
+    /// border is all the same get filled in, the others get split into smaller tiles.
+    Subdivide,
+}
+
+/// Settings for perturbation based deep zoom generation.
+/// The center is stored as decimal strings so no precision is lost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeepZoom {
+    /// The real value of the center of the image.
+    pub center_real: String,
+    /// The imaginary value of the center of the image.
+    pub center_imaginary: String,
+    /// The distance from the center to the top & bottom edges of the image in math space.
+    pub radius: f64,
+    /// The angle (in degrees) the image is rotated by.
+    #[serde(default)]
+    pub rotation: f64,
+}
+
+impl DeepZoom {
+
+    /// Gets the closest math frame to the deep zoom frame (as far as f64 allows).
+    pub fn get_math_frame(&self, size_x: u32, size_y: u32) -> MathFrame {
+        let view = View {
+            center: Complex {
+                real: self.center_real.parse::<f64>().unwrap_or_default(),
+                imaginary: self.center_imaginary.parse::<f64>().unwrap_or_default(),
+            },
+            radius: self.radius,
+            rotation: self.rotation,
+        };
+        return view.get_math_frame(size_x, size_y);
+    }
+
+}
+
+/// A view of math space given by its center, size & rotation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct View {
+    /// The point in the center of the image.
+    pub center: Complex<f64>,
+    /// The distance from the center to the top & bottom edges of the image in math space.
+    pub radius: f64,
+    /// The angle (in degrees) the image is rotated by, counter clockwise in math space.
+    pub rotation: f64,
+}
+
+impl View {
+
+    /// Gets the math frame which shows the view in a `size_x` x `size_y` image (with square pixels.)
+    pub fn get_math_frame(&self, size_x: u32, size_y: u32) -> MathFrame {
+        let pixel_size = 2.0 * self.radius / (size_y.max(2) - 1) as f64;
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+
+        let mut math_frame = MathFrame {
+            factor_x: pixel_size * cos,
+            factor_y: pixel_size * cos,
+            shear_x: -pixel_size * sin,
+            shear_y: pixel_size * sin,
+            offset_x: 0.0,
+            offset_y: 0.0,
+        };
+
+        // Places the center of the view in the middle of the image
+        let middle = math_frame.get_delta((size_x as f64 - 1.0) / 2.0, (size_y as f64 - 1.0) / 2.0);
+        math_frame.offset_x = self.center.real - middle.real;
+        math_frame.offset_y = self.center.imaginary - middle.imaginary;
+        return math_frame;
+    }
+
+}
+
+/// Settings for orbit density (Buddhabrot) rendering.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Buddhabrot {
+    /// The amount of random orbits to sample.
+    pub orbits: u64,
+    /// The iteration limit for the red, green & blue channels.
+    pub channel_max_i: [u64; 3],
+    /// Uses the orbits which don't escape instead of the ones which do.
+    pub anti: bool,
+    /// The seed for the random numbers, the same seed always gives the same image.
+    pub seed: u64,
+    /// The exponent applied to the normalized density of each channel.
+    pub gamma: f64,
+}
+
+/// The shapes orbit traps can have.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TrapShape {
+    /// The distance to the center of the trap.
+    #[default]
+    Point,
+    /// The distance to a line through the center of the trap.
+    Line,
+    /// The distance to two perpendicular lines crossing at the center of the trap.
+    Cross,
+    /// The distance to a circle around the center of the trap.
+    Circle,
+    /// How dark an image placed over the center of the trap is where the orbit lands on it.
+    Image,
+}
+
+/// Settings for orbit trap coloring.
+/// Pixels get colored by how close their orbit gets to the trap instead of their iteration count.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrbitTrap {
+    /// The shape of the trap.
+    pub shape: TrapShape,
+    /// The center of the trap.
+    pub center: Complex<f64>,
+    /// The angle of the line, cross or image in degrees.
+    pub angle: f64,
+    /// The radius of the circle & half the width of the image. Distances are measured in units of this.
+    pub radius: f64,
+    /// The path of the image used by image traps.
+    pub image: Option<String>,
+}
+
 /// Struct for factor & offset for math space
 /// This is used to calculate where each pixel is mapped to
+/// Pixels are mapped with an affine transform, the shear values are only set for rotated views:
+///   real      = factor_x * x + shear_x * y + offset_x
+///   imaginary = shear_y * x + factor_y * y + offset_y
+/// Values are stored as `f64` and get converted to the configured `Precision` during generation.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 #[repr(C)]
 pub struct MathFrame {
     /// This factor is in pixel space. (Offset in x axis)
-    pub factor_x: f32,
+    pub factor_x: f64,
     /// This factor is in pixel space. (Offset in y axis)
-    pub factor_y: f32,
+    pub factor_y: f64,
+
+    /// This factor is in pixel space. (Change of the real value per pixel in the y axis)
+    #[serde(default)]
+    pub shear_x: f64,
+    /// This factor is in pixel space. (Change of the imaginary value per pixel in the x axis)
+    #[serde(default)]
+    pub shear_y: f64,
 
     /// This offset is in math space. (Offset in x axis)
-    pub offset_x: f32,
+    pub offset_x: f64,
     /// This offset is in math space. (Offset in y axis)
-    pub offset_y: f32,
+    pub offset_y: f64,
+
+}
+
+impl MathFrame {
+
+    /// Gets the point in math space the pixel at (`x`, `y`) is mapped to.
+    pub fn get_point(&self, x: f64, y: f64) -> Complex<f64> {
+        let delta = self.get_delta(x, y);
+        return Complex {
+            real: delta.real + self.offset_x,
+            imaginary: delta.imaginary + self.offset_y,
+        };
+    }
+
+    /// Gets the distance in math space covered by moving `x` pixels across & `y` pixels down.
+    pub fn get_delta(&self, x: f64, y: f64) -> Complex<f64> {
+        return Complex {
+            real: self.factor_x * x + self.shear_x * y,
+            imaginary: self.shear_y * x + self.factor_y * y,
+        };
+    }
+
+    /// Gets the pixel position a point in math space is mapped to (the inverse of `get_point`.)
+    pub fn get_pixel(&self, point: Complex<f64>) -> (f64, f64) {
+        let (real, imaginary) = (point.real - self.offset_x, point.imaginary - self.offset_y);
+        let determinant = self.factor_x * self.factor_y - self.shear_x * self.shear_y;
+        return (
+            (self.factor_y * real - self.shear_x * imaginary) / determinant,
+            (self.factor_x * imaginary - self.shear_y * real) / determinant,
+        );
+    }
+
+    /// Scales the factors so an image of `new_size` shows the same area as an image of `old_size`.
+    pub fn resize(&self, old_size: (u32, u32), new_size: (u32, u32)) -> Self {
+        let scale_x = (old_size.0.max(2) - 1) as f64 / (new_size.0.max(2) - 1) as f64;
+        let scale_y = (old_size.1.max(2) - 1) as f64 / (new_size.1.max(2) - 1) as f64;
+        return Self {
+            factor_x: self.factor_x * scale_x,
+            factor_y: self.factor_y * scale_y,
+            shear_x: self.shear_x * scale_y,
+            shear_y: self.shear_y * scale_x,
+            offset_x: self.offset_x,
+            offset_y: self.offset_y,
+        };
+    }
+
+    /// Gets the width of a pixel in math space.
+    pub fn get_pixel_size(&self) -> f64 {
+        return self.factor_x.hypot(self.shear_y);
+    }
+
+    /// Gets the frame which shows the area `width` x `height` (starting at `offset_x`, `offset_y`) of math space
+    /// in a `size_x` x `size_y` image.
+    /// Unless `stretch` is set, both axes use the larger pixel size so pixels stay square. The shorter side of
+    /// the area gets extended around its center so all of it is still shown.
+    pub fn fit(width: f64, height: f64, offset_x: f64, offset_y: f64, size_x: u32, size_y: u32, stretch: bool) -> Self {
+        let steps_x = (size_x.max(2) - 1) as f64;
+        let steps_y = (size_y.max(2) - 1) as f64;
+
+        let (mut factor_x, mut factor_y) = (width / steps_x, height / steps_y);
+        if !stretch {
+            let pixel_size = factor_x.abs().max(factor_y.abs());
+            factor_x = pixel_size.copysign(factor_x);
+            factor_y = pixel_size.copysign(factor_y);
+        }
+
+        return Self {
+            factor_x,
+            factor_y,
+            shear_x: 0.0,
+            shear_y: 0.0,
+            offset_x: offset_x + (width - factor_x * steps_x) / 2.0,
+            offset_y: offset_y + (height - factor_y * steps_y) / 2.0,
+        };
+    }
 
 }
 
@@ -104,6 +534,11 @@ pub struct PushConstants {
     /// This factor is in math space. (Offset in y axis)
     pub factor_y: f32,
 
+    /// This factor is in math space. (Change of the real value in the y axis)
+    pub shear_x: f32,
+    /// This factor is in math space. (Change of the imaginary value in the x axis)
+    pub shear_y: f32,
+
     /// This offset is in math space. (Offset in x axis)
     pub offset_x: f32,
     /// This offset is in math space. (Offset in y axis)
@@ -116,20 +551,165 @@ pub struct PushConstants {
     // /// meant to start on.
     // pub starting_line: u32,
 
+    /// The real value of `c` for julia sets.
+    pub c_real: f32,
+    /// The imaginary value of `c` for julia sets.
+    pub c_imaginary: f32,
+
+    /// The real value of the center of the orbit trap.
+    pub trap_center_real: f32,
+    /// The imaginary value of the center of the orbit trap.
+    pub trap_center_imaginary: f32,
+
+    /// How close z has to come back to a saved value to count as a cycle (0 skips the check.)
+    pub periodicity_tolerance: f32,
+
+}
+
+/// Struct for handling push constants when generating with `Precision::F64`.
+/// Has the same layout as the `params` block of the shader when it uses doubles.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, BufferContents)]
+#[repr(C)]
+pub struct PushConstants64 {
+    /// This factor is in math space. (Offset in x axis)
+    pub factor_x: f64,
+    /// This factor is in math space. (Offset in y axis)
+    pub factor_y: f64,
+
+    /// This factor is in math space. (Change of the real value in the y axis)
+    pub shear_x: f64,
+    /// This factor is in math space. (Change of the imaginary value in the x axis)
+    pub shear_y: f64,
+
+    /// This offset is in math space. (Offset in x axis)
+    pub offset_x: f64,
+    /// This offset is in math space. (Offset in y axis)
+    pub offset_y: f64,
+
+    /// Amount of lines to generate (in pixel space)
+    pub amnt_of_lines: u32,
+
+    /// Pads the struct to the alignment of `f64`.
+    pub _padding: u32,
+
+    /// The real value of `c` for julia sets.
+    pub c_real: f64,
+    /// The imaginary value of `c` for julia sets.
+    pub c_imaginary: f64,
+
+    /// The real value of the center of the orbit trap.
+    pub trap_center_real: f64,
+    /// The imaginary value of the center of the orbit trap.
+    pub trap_center_imaginary: f64,
+
+    /// How close z has to come back to a saved value to count as a cycle (0 skips the check.)
+    pub periodicity_tolerance: f64,
+
+}
+
+/// Struct for the settings buffer of the shader.
+/// These are the values which can change between images without changing the shader, so images which only
+/// differ in them reuse the compiled pipeline. Has the same layout as the `Settings` block of the shader.
+#[derive(Debug, Clone, Default, BufferContents)]
+#[repr(C)]
+pub struct GpuSettings {
+    /// The background color (RGBA from 0 to 1)
+    pub background: [f32; 4],
+    /// The foreground color (RGBA from 0 to 1)
+    pub foreground: [f32; 4],
+    /// The direction of the orbit trap
+    pub trap_direction: [f32; 2],
+    /// The radius of the orbit trap
+    pub trap_radius: f32,
+    /// The limit passed to the escape condition
+    pub escape_limit: f32,
+    /// How close (in pixels) a pixel has to be to the set to count as the boundary
+    pub boundary_width: f32,
+    /// The angle the light comes from for the NORMAL shadow
+    pub light_angle: f32,
+    /// The height of the light for the NORMAL shadow
+    pub light_height: f32,
+    /// The rate color changes for the color formula
+    pub rate_of_color_change: f32,
+    /// The color value of one cycle of the color formula (for histogram & orbit trap coloring)
+    pub color_cycle: f32,
+    /// The width of the image in pixels
+    pub width: u32,
+    /// The maximum amount of iterations
+    pub max_i: u32,
+    /// The amount of samples per pixel
+    pub samples: u32,
+}
+
+/// The result of generating a single pixel, before any colors are applied.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sample {
+    /// The output value of the pixel (iteration count or travel distance)
+    pub z_output: f64,
+    /// The absolute value of z when iteration stopped
+    pub z_norm: f64,
+    /// If the pixel never escaped
+    pub is_inside: bool,
+    /// The index of the root the pixel converged to (for Newton type formulas)
+    pub root: Option<usize>,
+    /// The estimated distance to the set in math space (if the derivative was tracked & the pixel escaped)
+    pub distance: Option<f64>,
+    /// The direction pointing away from the set (if the derivative was tracked & the pixel escaped)
+    pub normal: Option<Complex<f64>>,
+    /// The closest the orbit got to the orbit trap in units of its radius (if an orbit trap is set)
+    pub trap: Option<f64>,
+}
+
+impl Sample {
+
+    /// Gets the distance estimate & normal of an escaped pixel from its final z & the derivative of z.
+    /// Returns `None` for both if z didn't escape past 1 (the estimate doesn't apply.)
+    pub fn get_distance_and_normal(z: Complex<f64>, dz: Complex<f64>) -> (Option<f64>, Option<Complex<f64>>) {
+        let z_norm = z.abs();
+        if z_norm <= 1.0 {
+            return (None, None);
+        }
+        let direction = z / dz;
+        let direction_norm = direction.abs();
+        let normal = match direction_norm.is_finite() && direction_norm > 0.0 {
+            true => Some(Complex { real: direction.real / direction_norm, imaginary: direction.imaginary / direction_norm }),
+            false => None,
+        };
+        return (Some(0.5 * z_norm * z_norm.ln() / dz.abs()), normal);
+    }
+
+    /// Gets the value the color of a pixel is based on.
+    /// This is the distance to the orbit trap if one is set, otherwise it is the same as `get_value`.
+    pub fn get_color_value(&self, config: &Config) -> f64 {
+        return self.trap.unwrap_or_else(|| self.get_value(config));
+    }
+
+    /// Gets the value passed to the color & shadow functions.
+    /// If smooth coloring is set, this is the normalized iteration count `n + 1 - log2(ln|z|)`.
+    /// This only applies to pixels which escaped past 1 (converging pixels aren't smoothed.)
+    pub fn get_value(&self, config: &Config) -> f64 {
+        if !config.smooth || config.travel_distance || self.is_inside || self.z_norm <= 1.0 {
+            return self.z_output;
+        }
+        return self.z_output + 1.0 - self.z_norm.ln().log2();
+    }
+
 }
 
 // Sets up Complex Struct
+// The float type defaults to `f32` however `f64` can be used for higher precision.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct Complex {
-    pub real: f32,
-    pub imaginary: f32,
+#[serde(deny_unknown_fields)]
+pub struct Complex<T = f32> {
+    pub real: T,
+    pub imaginary: T,
 }
 
 // Sets up Addition rules for Complex Numbers
-impl Add for Complex {
-    type Output = Complex;
+impl<T: Float> Add for Complex<T> {
+    type Output = Complex<T>;
 
-    fn add(self, other: Complex) -> Complex {
+    fn add(self, other: Complex<T>) -> Complex<T> {
         Complex {
             real : self.real + other.real,
             imaginary : self.imaginary + other.imaginary,
@@ -137,10 +717,10 @@ impl Add for Complex {
     }
 }
 
-impl Sub for Complex {
-    type Output = Complex;
+impl<T: Float> Sub for Complex<T> {
+    type Output = Complex<T>;
 
-    fn sub(self, other: Complex) -> Complex {
+    fn sub(self, other: Complex<T>) -> Complex<T> {
         Complex {
             real : self.real - other.real,
             imaginary : self.imaginary - other.imaginary,
@@ -149,10 +729,10 @@ impl Sub for Complex {
 }
 
 // Sets up Multiplication rules for Complex Numbers
-impl Mul for Complex {
-    type Output = Complex;
+impl<T: Float> Mul for Complex<T> {
+    type Output = Complex<T>;
 
-    fn mul(self, other: Complex) -> Complex {
+    fn mul(self, other: Complex<T>) -> Complex<T> {
         Complex {
             real : self.real * other.real - self.imaginary * other.imaginary,
             imaginary : self.real * other.imaginary + self.imaginary * other.real,
@@ -160,9 +740,296 @@ impl Mul for Complex {
     }
 }
 
-impl Complex {
+// Sets up Division rules for Complex Numbers
+impl<T: Float> Div for Complex<T> {
+    type Output = Complex<T>;
+
+    fn div(self, other: Complex<T>) -> Complex<T> {
+        let denominator = other.norm_sqr();
+        Complex {
+            real : (self.real * other.real + self.imaginary * other.imaginary) / denominator,
+            imaginary : (self.imaginary * other.real - self.real * other.imaginary) / denominator,
+        }
+    }
+}
+
+impl<T: Float> Neg for Complex<T> {
+    type Output = Complex<T>;
+
+    fn neg(self) -> Complex<T> {
+        Complex {
+            real : -self.real,
+            imaginary : -self.imaginary,
+        }
+    }
+}
+
+impl<T: Float> Complex<T> {
+    /// Creates a complex number with no imaginary part.
+    pub fn from_real(real: T) -> Self {
+        Complex {
+            real,
+            imaginary : T::zero(),
+        }
+    }
+
     // Sets up Comparison rules for Complex Numbers
-    pub fn is_greater(self, other: f32) -> bool {
+    pub fn is_greater(self, other: T) -> bool {
         (self.real * self.real + self.imaginary * self.imaginary) > (other * other)
     }
+
+    /// Gets the square of the absolute value of the complex number.
+    pub fn norm_sqr(self) -> T {
+        self.real * self.real + self.imaginary * self.imaginary
+    }
+
+    /// Gets the absolute value of the complex number.
+    pub fn abs(self) -> T {
+        self.real.hypot(self.imaginary)
+    }
+
+    /// Gets the angle of the complex number (between -pi & pi.)
+    pub fn arg(self) -> T {
+        self.imaginary.atan2(self.real)
+    }
+
+    /// Gets the complex conjugate.
+    pub fn conj(self) -> Self {
+        Complex {
+            real : self.real,
+            imaginary : -self.imaginary,
+        }
+    }
+
+    /// Calculates `e^z`.
+    pub fn exp(self) -> Self {
+        let length = self.real.exp();
+        Complex {
+            real : length * self.imaginary.cos(),
+            imaginary : length * self.imaginary.sin(),
+        }
+    }
+
+    /// Calculates the principal natural logarithm.
+    pub fn ln(self) -> Self {
+        Complex {
+            real : self.abs().ln(),
+            imaginary : self.arg(),
+        }
+    }
+
+    /// Calculates the principal square root.
+    pub fn sqrt(self) -> Self {
+        let length = self.abs().sqrt();
+        let angle = self.arg() / (T::one() + T::one());
+        Complex {
+            real : length * angle.cos(),
+            imaginary : length * angle.sin(),
+        }
+    }
+
+    /// Raises the complex number to a whole number power by repeated squaring.
+    /// The GLSL `power(Complex, int)` function does the same steps.
+    pub fn powi(self, exponent: i32) -> Self {
+        let mut result = Complex::from_real(T::one());
+        let mut base = match exponent < 0 {
+            true => Complex::from_real(T::one()) / self,
+            false => self,
+        };
+        let mut exponent = exponent.unsigned_abs();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exponent >>= 1;
+        }
+        return result;
+    }
+
+    /// Raises the complex number to a real power (using the principal value.)
+    pub fn powf(self, exponent: T) -> Self {
+        let length = self.abs().powf(exponent);
+        let angle = self.arg() * exponent;
+        Complex {
+            real : length * angle.cos(),
+            imaginary : length * angle.sin(),
+        }
+    }
+
+    /// Raises the complex number to a complex power (using the principal value.)
+    pub fn powc(self, exponent: Self) -> Self {
+        (exponent * self.ln()).exp()
+    }
+
+    pub fn sin(self) -> Self {
+        Complex {
+            real : self.real.sin() * self.imaginary.cosh(),
+            imaginary : self.real.cos() * self.imaginary.sinh(),
+        }
+    }
+
+    pub fn cos(self) -> Self {
+        Complex {
+            real : self.real.cos() * self.imaginary.cosh(),
+            imaginary : -self.real.sin() * self.imaginary.sinh(),
+        }
+    }
+
+    pub fn tan(self) -> Self {
+        self.sin() / self.cos()
+    }
+
+    pub fn sinh(self) -> Self {
+        Complex {
+            real : self.real.sinh() * self.imaginary.cos(),
+            imaginary : self.real.cosh() * self.imaginary.sin(),
+        }
+    }
+
+    pub fn cosh(self) -> Self {
+        Complex {
+            real : self.real.cosh() * self.imaginary.cos(),
+            imaginary : self.real.sinh() * self.imaginary.sin(),
+        }
+    }
+
+    pub fn tanh(self) -> Self {
+        self.sinh() / self.cosh()
+    }
+
+    /// Converts the complex number to one using a different float type.
+    pub fn cast<U: Float>(self) -> Complex<U> {
+        Complex {
+            real      : U::from(self.real).unwrap(),
+            imaginary : U::from(self.imaginary).unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_value() {
+        let config = Config { smooth: true, travel_distance: false, ..Config::default() };
+        let sample = Sample { z_output: 10.0, z_norm: 2.0_f64.exp(), ..Sample::default() };
+        assert!((sample.get_value(&config) - 10.0).abs() < 1e-12);
+
+        // A larger |z| means the pixel escaped earlier in the iteration
+        let further = Sample { z_norm: 4.0_f64.exp(), ..sample };
+        assert!((further.get_value(&config) - 9.0).abs() < 1e-12);
+
+        // Only escaped iteration counts get smoothed
+        assert_eq!(Sample { is_inside: true, ..sample }.get_value(&config), 10.0);
+        assert_eq!(sample.get_value(&Config { travel_distance: true, ..config.clone() }), 10.0);
+        assert_eq!(sample.get_value(&Config { smooth: false, ..config }), 10.0);
+    }
+
+    #[test]
+    fn distance_and_normal() {
+        let (distance, normal) = Sample::get_distance_and_normal(Complex::from_real(2.0), Complex { real: 0.0, imaginary: 4.0 });
+        assert!((distance.unwrap() - 0.25 * 2.0_f64.ln()).abs() < 1e-12);
+        // z / dz = -0.5i
+        let normal = normal.unwrap();
+        assert!(normal.real.abs() < 1e-12 && (normal.imaginary + 1.0).abs() < 1e-12);
+
+        // Pixels which didn't escape past 1 don't have an estimate
+        let (distance, normal) = Sample::get_distance_and_normal(Complex::from_real(0.5), Complex::from_real(1.0));
+        assert!(distance.is_none() && normal.is_none());
+
+        // A zero derivative doesn't have a direction
+        assert!(Sample::get_distance_and_normal(Complex::from_real(2.0), Complex::from_real(0.0)).1.is_none());
+    }
+
+    #[test]
+    fn powers() {
+        let z = Complex { real: 0.6, imaginary: -0.8 };
+        let close = |a: Complex<f64>, b: Complex<f64>| (a - b).abs() < 1e-12;
+
+        assert!(close(z.powi(0), Complex::from_real(1.0)));
+        assert!(close(z.powi(5), z * z * z * z * z));
+        assert!(close(z.powi(-2), Complex::from_real(1.0) / (z * z)));
+
+        // Real & complex powers agree with whole number powers
+        assert!(close(z.powf(3.0), z.powi(3)));
+        assert!(close(z.powc(Complex::from_real(-3.0)), z.powi(-3)));
+        assert!(close(z.powf(0.5), z.sqrt()));
+        assert!(close(z.powf(0.5).powi(2), z));
+    }
+
+    #[test]
+    fn fit_keeps_pixels_square() {
+        // A square area in a wide image gets extended on the real axis
+        let frame = MathFrame::fit(4.0, 4.0, -2.0, -2.0, 9, 5, false);
+        assert_eq!((frame.factor_x, frame.factor_y), (1.0, 1.0));
+        assert_eq!((frame.offset_x, frame.offset_y), (-4.0, -2.0));
+
+        // The center stays in the middle of the image
+        assert_eq!(frame.offset_x + frame.factor_x * 4.0, 0.0);
+        assert_eq!(frame.offset_y + frame.factor_y * 2.0, 0.0);
+    }
+
+    #[test]
+    fn fit_stretch() {
+        let frame = MathFrame::fit(4.0, 4.0, -2.0, -2.0, 9, 5, true);
+        assert_eq!((frame.factor_x, frame.factor_y), (0.5, 1.0));
+        assert_eq!((frame.offset_x, frame.offset_y), (-2.0, -2.0));
+
+        // Flipped axes keep their direction
+        let frame = MathFrame::fit(4.0, -4.0, -2.0, 2.0, 5, 5, false);
+        assert_eq!((frame.factor_x, frame.factor_y), (1.0, -1.0));
+        assert_eq!((frame.offset_x, frame.offset_y), (-2.0, 2.0));
+    }
+
+    #[test]
+    fn views() {
+        let view = View { center: Complex { real: -0.5, imaginary: 0.25 }, radius: 2.0, rotation: 0.0 };
+        let frame = view.get_math_frame(9, 5);
+        assert_eq!((frame.factor_x, frame.factor_y), (1.0, 1.0));
+        let center = frame.get_point(4.0, 2.0);
+        assert_eq!((center.real, center.imaginary), (-0.5, 0.25));
+
+        // Rotating a quarter turn moves the real axis onto the imaginary axis
+        let frame = View { rotation: 90.0, ..view }.get_math_frame(9, 5);
+        let delta = frame.get_delta(1.0, 0.0);
+        assert!(delta.real.abs() < 1e-12 && (delta.imaginary - 1.0).abs() < 1e-12);
+        assert!((frame.get_pixel_size() - 1.0).abs() < 1e-12);
+        let center = frame.get_point(4.0, 2.0);
+        assert!((center.real + 0.5).abs() < 1e-12 && (center.imaginary - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn pixels_and_points() {
+        let view = View { center: Complex { real: 0.3, imaginary: -0.2 }, radius: 1.5, rotation: 30.0 };
+        let frame = view.get_math_frame(16, 9);
+        for (x, y) in [(0.0, 0.0), (15.0, 8.0), (3.5, 7.25)] {
+            let (pixel_x, pixel_y) = frame.get_pixel(frame.get_point(x, y));
+            assert!((pixel_x - x).abs() < 1e-9 && (pixel_y - y).abs() < 1e-9);
+        }
+
+        // Deep zooms use the same frame as views
+        let deep_zoom = DeepZoom { center_real: "0.3".into(), center_imaginary: "-0.2".into(), radius: 1.5, rotation: 30.0 };
+        let deep_frame = deep_zoom.get_math_frame(16, 9);
+        assert_eq!((deep_frame.factor_x, deep_frame.shear_x), (frame.factor_x, frame.shear_x));
+        assert_eq!((deep_frame.offset_x, deep_frame.offset_y), (frame.offset_x, frame.offset_y));
+    }
+
+    #[test]
+    fn validate_rejects_unusable_settings() {
+        assert!(Config::default().validate().is_ok());
+
+        let constant = Config { polynomial: vec![3.0], ..Config::default() };
+        assert!(Config { gen_formula: "NEWTON".into(), ..constant.clone() }.validate().is_err());
+        assert!(Config { gen_formula: "NOVA".into(), ..constant.clone() }.validate().is_err());
+        assert!(Config { gen_formula: "NOVA".into(), polynomial: vec![1.0, 0.0, 0.0, -1.0], ..constant }.validate().is_ok());
+
+        assert!(Config { bailout: f64::NAN, ..Config::default() }.validate().is_err());
+        assert!(Config { polynomial: vec![1.0, f64::INFINITY], ..Config::default() }.validate().is_err());
+        assert!(Config { c_init: Some(Complex { real: f64::NEG_INFINITY, imaginary: 0.0 }), ..Config::default() }.validate().is_err());
+
+        let (kind, _) = Config { gpu: true, strategy: Strategy::Subdivide, ..Config::default() }.validate().unwrap_err();
+        assert_eq!(kind, ErrorKind::ArgumentConflict);
+    }
 }